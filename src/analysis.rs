@@ -0,0 +1,568 @@
+use crate::ast::{ASTNode, Argument, Name, PrintTarget};
+use crate::lexer::Span;
+use std::collections::{HashMap, HashSet};
+
+// ===== Static analysis =====
+// Checks that look at a parsed program without running it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub message: String,
+    pub span: Span,
+}
+
+impl std::fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} at {}..{}",
+            self.message, self.span.start, self.span.end
+        )
+    }
+}
+
+// Reports variables read before any statement defines them, once per name.
+// `known` lists names the host provides up front (a pre-filled world, a
+// resolver), which the program cannot see.
+pub fn undefined_variables(ast: &[ASTNode], known: &[&str]) -> Vec<Diagnostic> {
+    let mut defined: HashSet<Name> = known.iter().map(|&s| Name::from(s)).collect();
+    let mut out = Vec::new();
+    check_undefined(ast, &mut defined, &mut out);
+    out
+}
+
+fn check_undefined(ast: &[ASTNode], defined: &mut HashSet<Name>, out: &mut Vec<Diagnostic>) {
+    for node in ast {
+        let read = match node {
+            ASTNode::Call { target, args, .. } => {
+                for arg in args {
+                    if let Argument::Variable(name) = arg {
+                        report_undefined(node, name, defined, out);
+                    }
+                }
+                if let Some(target) = target {
+                    defined.insert(target.clone());
+                }
+                None
+            }
+            ASTNode::Let { name, .. } | ASTNode::Input { variable: name, .. } => {
+                defined.insert(name.clone());
+                None
+            }
+            ASTNode::ListPopFront {
+                variable, target, ..
+            }
+            | ASTNode::ListPopBack {
+                variable, target, ..
+            } => {
+                report_undefined(node, variable, defined, out);
+                defined.insert(target.clone());
+                None
+            }
+            ASTNode::Print {
+                target: PrintTarget::Variable(name),
+                ..
+            }
+            | ASTNode::Assert {
+                condition: Argument::Variable(name),
+                ..
+            }
+            | ASTNode::ListPush { variable: name, .. }
+            | ASTNode::ListPushFront { variable: name, .. }
+            | ASTNode::SetInsert { variable: name, .. } => Some(name),
+            // The VM branches on and merges a name that has no value yet,
+            // even in strict mode, so neither counts as a read.
+            ASTNode::Branch { .. }
+            | ASTNode::Merge { .. }
+            | ASTNode::Print { .. }
+            | ASTNode::Exit { .. }
+            | ASTNode::Assert { .. } => None,
+        };
+        if let Some(name) = read {
+            report_undefined(node, name, defined, out);
+        }
+        // Branch bodies run in place, so their definitions stay visible.
+        if let ASTNode::Branch { body, .. } = node {
+            check_undefined(body, defined, out);
+        }
+    }
+}
+
+fn report_undefined(
+    node: &ASTNode,
+    name: &Name,
+    defined: &mut HashSet<Name>,
+    out: &mut Vec<Diagnostic>,
+) {
+    if defined.insert(name.clone()) {
+        out.push(Diagnostic {
+            message: format!("`{}` is used before it is defined", name),
+            span: node.span(),
+        });
+    }
+}
+
+// ===== Lints =====
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Rule {
+    // Defined (by `let` or `input`) but never read.
+    UnusedVariable,
+    // A `branch` that no later `merge` picks up.
+    UnmergedBranch,
+    // A `merge` with no open branch for the variable.
+    MergeWithoutBranch,
+    // Redefined before the previous value was read.
+    ShadowedName,
+    // Defined inside a branch body when it was already defined outside it.
+    // Bodies have no scope of their own, so this rebinds the outer variable.
+    ShadowsOuter,
+    // Statements after `exit`.
+    Unreachable,
+    // An operation given a value of the wrong type; see `type_mismatches`.
+    TypeMismatch,
+}
+
+impl Rule {
+    pub const ALL: [Rule; 7] = [
+        Rule::UnusedVariable,
+        Rule::UnmergedBranch,
+        Rule::MergeWithoutBranch,
+        Rule::ShadowedName,
+        Rule::ShadowsOuter,
+        Rule::Unreachable,
+        Rule::TypeMismatch,
+    ];
+    pub fn name(self) -> &'static str {
+        match self {
+            Rule::UnusedVariable => "unused-variable",
+            Rule::UnmergedBranch => "unmerged-branch",
+            Rule::MergeWithoutBranch => "merge-without-branch",
+            Rule::ShadowedName => "shadowed-name",
+            Rule::ShadowsOuter => "shadows-outer",
+            Rule::Unreachable => "unreachable",
+            Rule::TypeMismatch => "type-mismatch",
+        }
+    }
+    pub fn from_name(name: &str) -> Option<Rule> {
+        Rule::ALL.into_iter().find(|rule| rule.name() == name)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Lint {
+    pub rule: Rule,
+    pub diagnostic: Diagnostic,
+}
+
+#[derive(Default)]
+struct Linter {
+    // Latest definition of each variable, whether it has been read since,
+    // and how many branch bodies deep it was.
+    defs: HashMap<Name, (Span, bool, usize)>,
+    // Variables of the branch bodies being walked, innermost last.
+    bodies: Vec<Name>,
+    // Branches closed but not merged yet, as the VM tracks them.
+    open: HashMap<Name, Span>,
+    lints: Vec<Lint>,
+}
+
+impl Linter {
+    fn push(&mut self, rule: Rule, message: String, span: Span) {
+        self.lints.push(Lint {
+            rule,
+            diagnostic: Diagnostic { message, span },
+        });
+    }
+
+    fn read(&mut self, name: &str) {
+        if let Some((_, used, _)) = self.defs.get_mut(name) {
+            *used = true;
+        }
+    }
+
+    fn define(&mut self, name: &Name, span: Span) {
+        let depth = self.bodies.len();
+        let Some((_, used, outer)) = self.defs.insert(name.clone(), (span, false, depth)) else {
+            return;
+        };
+        if !used {
+            self.push(
+                Rule::ShadowedName,
+                format!("`{}` is redefined before its previous value is used", name),
+                span,
+            );
+        }
+        if let Some(branch) = self.bodies.last().filter(|_| outer < depth) {
+            let message = format!(
+                "`{}` in the branch on `{}` rebinds the outer `{}`; branch bodies have no scope of their own",
+                name, branch, name
+            );
+            self.push(Rule::ShadowsOuter, message, span);
+        }
+    }
+
+    // Returns whether the block ran into an `exit`.
+    fn block(&mut self, ast: &[ASTNode]) -> bool {
+        for (i, node) in ast.iter().enumerate() {
+            let exited = match node {
+                ASTNode::Let { name, .. } | ASTNode::Input { variable: name, .. } => {
+                    self.define(name, node.span());
+                    false
+                }
+                ASTNode::Print {
+                    target: PrintTarget::Variable(name),
+                    ..
+                } => {
+                    self.read(name);
+                    false
+                }
+                ASTNode::Print { .. } => false,
+                ASTNode::Assert { condition, .. } => {
+                    if let Argument::Variable(name) = condition {
+                        self.read(name);
+                    }
+                    false
+                }
+                ASTNode::ListPush { variable, .. }
+                | ASTNode::ListPushFront { variable, .. }
+                | ASTNode::SetInsert { variable, .. } => {
+                    self.read(variable);
+                    false
+                }
+                ASTNode::ListPopFront {
+                    variable, target, ..
+                }
+                | ASTNode::ListPopBack {
+                    variable, target, ..
+                } => {
+                    self.read(variable);
+                    self.define(target, node.span());
+                    false
+                }
+                ASTNode::Branch { variable, body, .. } => {
+                    self.read(variable);
+                    self.bodies.push(variable.clone());
+                    let exited = self.block(body);
+                    self.bodies.pop();
+                    // Closing a branch adopts every branch still open; they
+                    // are merged along with it.
+                    self.open.clear();
+                    self.open.insert(variable.clone(), node.span());
+                    exited
+                }
+                ASTNode::Merge { variable, .. } => {
+                    self.read(variable);
+                    if self.open.remove(variable).is_none() {
+                        self.push(
+                            Rule::MergeWithoutBranch,
+                            format!("`merge {}` has no open branch to merge", variable),
+                            node.span(),
+                        );
+                    }
+                    false
+                }
+                ASTNode::Exit { .. } => true,
+                ASTNode::Call { target, args, .. } => {
+                    for arg in args {
+                        if let Argument::Variable(name) = arg {
+                            self.read(name);
+                        }
+                    }
+                    if let Some(target) = target {
+                        self.define(target, node.span());
+                    }
+                    false
+                }
+            };
+            if exited {
+                if let Some(next) = ast.get(i + 1) {
+                    let end = ast[ast.len() - 1].span().end;
+                    self.push(
+                        Rule::Unreachable,
+                        "unreachable statement".to_string(),
+                        Span::new(next.span().start, end),
+                    );
+                }
+                return true;
+            }
+        }
+        false
+    }
+}
+
+// Runs every rule; callers filter by `Lint::rule`. Results are in source order.
+pub fn lint(ast: &[ASTNode]) -> Vec<Lint> {
+    let mut linter = Linter::default();
+    linter.block(ast);
+    let unused: Vec<_> = linter
+        .defs
+        .iter()
+        .filter(|(_, (_, used, _))| !used)
+        .map(|(name, (span, _, _))| (name.clone(), *span))
+        .collect();
+    for (name, span) in unused {
+        linter.push(
+            Rule::UnusedVariable,
+            format!("`{}` is never used", name),
+            span,
+        );
+    }
+    for diagnostic in type_mismatches(ast) {
+        linter.lints.push(Lint {
+            rule: Rule::TypeMismatch,
+            diagnostic,
+        });
+    }
+    let open: Vec<_> = linter.open.drain().collect();
+    for (name, span) in open {
+        linter.push(
+            Rule::UnmergedBranch,
+            format!("branch on `{}` is never merged", name),
+            span,
+        );
+    }
+    linter
+        .lints
+        .sort_by_key(|l| (l.diagnostic.span.start, l.diagnostic.span.end));
+    linter.lints
+}
+
+// ===== Type inference =====
+// Follows types through the program without annotations: literals and
+// `input` give a variable its type, and builtins give their result's. Branch
+// bodies run in place and merging changes no values, so one pass in source
+// order sees what a run would. Whatever the pass cannot know (a popped
+// element, `jsonparse`, a host-provided variable) is left unknown and never
+// reported.
+pub fn type_mismatches(ast: &[ASTNode]) -> Vec<Diagnostic> {
+    let mut types: HashMap<Name, &'static str> = HashMap::new();
+    let mut out = Vec::new();
+    infer_block(ast, &mut types, &mut out);
+    out
+}
+
+const LIST: &[&str] = &["list"];
+const LIST_OR_RANGE: &[&str] = &["list", "range"];
+const SET: &[&str] = &["set"];
+const BOOL: &[&str] = &["bool"];
+const INT: &[&str] = &["int"];
+const STR: &[&str] = &["str"];
+const CHAR: &[&str] = &["char"];
+const ARRAY: &[&str] = &["intarray", "floatarray"];
+const NUMBER: &[&str] = &["int", "float"];
+const SEQUENCE: &[&str] = &["list", "range", "intarray", "floatarray", "set"];
+const SIZED: &[&str] = &[
+    "str",
+    "list",
+    "range",
+    "intarray",
+    "floatarray",
+    "set",
+    "map",
+];
+
+fn infer_block(
+    ast: &[ASTNode],
+    types: &mut HashMap<Name, &'static str>,
+    out: &mut Vec<Diagnostic>,
+) {
+    for node in ast {
+        let span = node.span();
+        match node {
+            ASTNode::Let { name, value, .. } => {
+                types.insert(name.clone(), value.type_name());
+            }
+            ASTNode::Input {
+                variable,
+                input_type,
+                ..
+            } => {
+                types.insert(variable.clone(), input_type.name());
+            }
+            ASTNode::ListPush { variable, .. } => {
+                expect_variable(types, variable, "listpush", LIST, span, out)
+            }
+            ASTNode::ListPushFront { variable, .. } => {
+                expect_variable(types, variable, "listpushfront", LIST, span, out)
+            }
+            ASTNode::SetInsert { variable, .. } => {
+                expect_variable(types, variable, "setinsert", SET, span, out)
+            }
+            ASTNode::ListPopFront {
+                variable, target, ..
+            }
+            | ASTNode::ListPopBack {
+                variable, target, ..
+            } => {
+                let statement = node.kind();
+                expect_variable(types, variable, statement, LIST_OR_RANGE, span, out);
+                types.remove(target);
+            }
+            ASTNode::Assert {
+                condition: Argument::Variable(name),
+                ..
+            } => expect_variable(types, name, "assert", BOOL, span, out),
+            ASTNode::Assert {
+                condition: Argument::Value(value),
+                ..
+            } => {
+                if value.type_name() != "bool" {
+                    out.push(Diagnostic {
+                        message: format!(
+                            "`assert` needs a bool, but the condition is {}",
+                            article(value.type_name())
+                        ),
+                        span,
+                    });
+                }
+            }
+            ASTNode::Call {
+                target,
+                function,
+                args,
+                ..
+            } => {
+                let found: Vec<Option<&'static str>> = args
+                    .iter()
+                    .map(|arg| match arg {
+                        Argument::Variable(name) => types.get(name).copied(),
+                        Argument::Value(value) => Some(value.type_name()),
+                    })
+                    .collect();
+                for (i, (arg, found)) in args.iter().zip(&found).enumerate() {
+                    let (Some(found), Some(expected)) = (found, parameter(function, i)) else {
+                        continue;
+                    };
+                    if expected.contains(found) {
+                        continue;
+                    }
+                    let actual = match arg {
+                        Argument::Variable(name) => {
+                            format!("`{}` is {} here", name, article(found))
+                        }
+                        Argument::Value(_) => format!("it is {}", article(found)),
+                    };
+                    out.push(Diagnostic {
+                        message: format!(
+                            "argument {} of `{}` should be {}, but {}",
+                            i + 1,
+                            function,
+                            one_of(expected),
+                            actual
+                        ),
+                        span,
+                    });
+                }
+                if let Some(target) = target {
+                    match returns(function, &found) {
+                        Some(ty) => types.insert(target.clone(), ty),
+                        None => types.remove(target),
+                    };
+                }
+            }
+            ASTNode::Branch { body, .. } => infer_block(body, types, out),
+            ASTNode::Print { .. } | ASTNode::Merge { .. } | ASTNode::Exit { .. } => {}
+        }
+    }
+}
+
+fn expect_variable(
+    types: &HashMap<Name, &'static str>,
+    variable: &str,
+    statement: &str,
+    expected: &[&str],
+    span: Span,
+    out: &mut Vec<Diagnostic>,
+) {
+    if let Some(found) = types.get(variable)
+        && !expected.contains(found)
+    {
+        out.push(Diagnostic {
+            message: format!(
+                "`{}` needs {}, but `{}` is {} here",
+                statement,
+                one_of(expected),
+                variable,
+                article(found)
+            ),
+            span,
+        });
+    }
+}
+
+// What argument `i` of a builtin must be, as `Value::type_name`s. `None`
+// when the builtin takes anything there or is not known.
+fn parameter(function: &str, i: usize) -> Option<&'static [&'static str]> {
+    Some(match (function, i) {
+        ("len", 0) => SIZED,
+        ("list" | "sort", 0) => SEQUENCE,
+        ("sum", 0) => &["list", "range", "intarray", "floatarray"],
+        ("intarray" | "floatarray", 0) => &["int", "list", "range", "intarray", "floatarray"],
+        ("arradd" | "arrmul" | "fill", 0) => ARRAY,
+        ("arradd" | "arrmul" | "fill", 1) => NUMBER,
+        ("range" | "randint" | "randseed" | "chr" | "datediff", _) => INT,
+        ("chars" | "b64decode" | "dateparse" | "format" | "getenv" | "jsonparse", 0) => STR,
+        ("httpget" | "sha256" | "md5" | "csvread" | "csvwrite" | "exec", 0) => STR,
+        ("charat", 0) | ("datefmt" | "dateparse", 1) => STR,
+        ("charat", 1) | ("datefmt" | "dateadd", 0) => INT,
+        ("dateadd", 1) => &["int", "str"],
+        ("ord", 0) => CHAR,
+        ("b64encode", 0) => &["str", "list"],
+        ("csvread", 1) => BOOL,
+        ("csvwrite" | "exec", 1) => LIST,
+        ("decadd" | "decsub" | "decmul" | "decdiv" | "decround", 0)
+        | ("decadd" | "decsub" | "decmul" | "decdiv", 1) => &["decimal", "int"],
+        ("decround", 1) => INT,
+        _ => return None,
+    })
+}
+
+// The type of a builtin's result given its arguments' types, when that is
+// certain. Int results assume an overflow policy that keeps ints ints: under
+// `--overflow float`, `sum`, `arradd` and `arrmul` of ints can come back as
+// floats. No parameter takes a float but not an int, so that can only hide a
+// report, never cause a wrong one.
+fn returns(function: &str, args: &[Option<&'static str>]) -> Option<&'static str> {
+    let first = args.first().copied().flatten();
+    Some(match function {
+        "len" | "ord" | "randint" | "now" | "dateparse" | "dateadd" | "datediff" => "int",
+        "eq" => "bool",
+        "rand" => "float",
+        "charat" | "chr" => "char",
+        "range" => "range",
+        "chars" | "csvread" | "exec" | "list" => "list",
+        "intarray" => "intarray",
+        "floatarray" => "floatarray",
+        "decimal" | "decadd" | "decsub" | "decmul" | "decdiv" | "decround" => "decimal",
+        "b64encode" | "datefmt" | "format" | "jsonstr" | "typeof" | "uuid" | "httpget"
+        | "sha256" | "md5" => "str",
+        "arradd" | "arrmul" | "fill" | "deepcopy" => first?,
+        "sort" => match first? {
+            "intarray" | "floatarray" => first?,
+            _ => "list",
+        },
+        "sum" => match first? {
+            "intarray" | "range" => "int",
+            "floatarray" => "float",
+            _ => return None,
+        },
+        _ => return None,
+    })
+}
+
+fn article(type_name: &str) -> String {
+    let a = if type_name.starts_with(['a', 'e', 'i', 'o', 'u']) {
+        "an"
+    } else {
+        "a"
+    };
+    format!("{} {}", a, type_name)
+}
+
+// `a str`, `a list or range`, `an int, list or range`.
+fn one_of(types: &[&str]) -> String {
+    match types {
+        [] => String::new(),
+        [only] => article(only),
+        [rest @ .., last] => format!("{} or {}", article(&rest.join(", ")), last),
+    }
+}
@@ -0,0 +1,480 @@
+use crate::lexer::Span;
+use crate::value::{Shared, Value};
+use serde::{Serialize, Serializer};
+use std::{
+    borrow::Borrow,
+    cmp::Ordering,
+    fmt,
+    hash::{Hash, Hasher},
+};
+
+// ===== AST =====
+// A variable or function name. The parser interns them, so every mention of
+// a name in one source shares an allocation and cloning a node copies no
+// text. `Program::from_ast` also numbers the variables of a program from 0
+// (see `Name::symbol`), so that names compare as integers once compiled.
+// Equality, hashing and ordering go by the text alone.
+#[derive(Clone)]
+pub struct Name {
+    text: Shared<str>,
+    symbol: u32,
+}
+
+const NO_SYMBOL: u32 = u32::MAX;
+
+impl Name {
+    pub fn as_str(&self) -> &str {
+        &self.text
+    }
+    // The variable's number in the program it was compiled into; `None` for
+    // function names and for names that have not been through
+    // `Program::from_ast`.
+    pub fn symbol(&self) -> Option<usize> {
+        (self.symbol != NO_SYMBOL).then_some(self.symbol as usize)
+    }
+    pub(crate) fn with_symbol(&self, symbol: usize) -> Name {
+        Name {
+            text: self.text.clone(),
+            symbol: u32::try_from(symbol).unwrap_or(NO_SYMBOL),
+        }
+    }
+}
+
+impl From<&str> for Name {
+    fn from(text: &str) -> Self {
+        Name {
+            text: text.into(),
+            symbol: NO_SYMBOL,
+        }
+    }
+}
+
+impl From<String> for Name {
+    fn from(text: String) -> Self {
+        Name {
+            text: text.into(),
+            symbol: NO_SYMBOL,
+        }
+    }
+}
+
+impl std::ops::Deref for Name {
+    type Target = str;
+    fn deref(&self) -> &str {
+        &self.text
+    }
+}
+
+impl AsRef<str> for Name {
+    fn as_ref(&self) -> &str {
+        &self.text
+    }
+}
+
+impl Borrow<str> for Name {
+    fn borrow(&self) -> &str {
+        &self.text
+    }
+}
+
+impl PartialEq for Name {
+    fn eq(&self, other: &Self) -> bool {
+        self.text == other.text
+    }
+}
+impl Eq for Name {}
+
+impl Hash for Name {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.text.hash(state)
+    }
+}
+
+impl PartialOrd for Name {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+impl Ord for Name {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.text.cmp(&other.text)
+    }
+}
+
+impl fmt::Display for Name {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.text)
+    }
+}
+
+impl fmt::Debug for Name {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&*self.text, f)
+    }
+}
+
+impl Serialize for Name {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.text)
+    }
+}
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(tag = "type", content = "value", rename_all = "lowercase")]
+pub enum PrintTarget {
+    Variable(Name),
+    Value(Value),
+}
+
+// An argument to a builtin call.
+#[derive(Clone, Debug, Serialize)]
+#[serde(tag = "type", content = "value", rename_all = "lowercase")]
+pub enum Argument {
+    Variable(Name),
+    Value(Value),
+}
+
+// What `input` stores: the trimmed line as is, or parsed as a number.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum InputType {
+    #[default]
+    Str,
+    Int,
+    Float,
+}
+
+impl InputType {
+    pub fn name(self) -> &'static str {
+        match self {
+            InputType::Str => "str",
+            InputType::Int => "int",
+            InputType::Float => "float",
+        }
+    }
+}
+
+// Serialized with a `kind` field matching `ASTNode::kind`.
+#[derive(Clone, Debug, Serialize)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum ASTNode {
+    // Defines `name`, or rebinds it when it already exists. There is one
+    // world and no block scope: a `let` in a branch body rebinds the same
+    // variable as one at top level, and the lint `shadows-outer` points it
+    // out.
+    Let {
+        name: Name,
+        value: Value,
+        span: Span,
+    },
+    Branch {
+        variable: Name,
+        body: Vec<ASTNode>,
+        span: Span,
+    },
+    Merge {
+        variable: Name,
+        span: Span,
+    },
+    Print {
+        target: PrintTarget,
+        span: Span,
+    },
+    // `input int "age: " x;` parses the line; plain `input` keeps a string.
+    Input {
+        prompt: Option<String>,
+        input_type: InputType,
+        variable: Name,
+        span: Span,
+    },
+    ListPush {
+        variable: Name,
+        value: Value,
+        span: Span,
+    },
+    ListPushFront {
+        variable: Name,
+        value: Value,
+        span: Span,
+    },
+    // `listpopfront q item;` moves the first element of `q` into `item`,
+    // or stores nil when `q` is empty. `listpopback` takes the last.
+    ListPopFront {
+        variable: Name,
+        target: Name,
+        span: Span,
+    },
+    ListPopBack {
+        variable: Name,
+        target: Name,
+        span: Span,
+    },
+    SetInsert {
+        variable: Name,
+        value: Value,
+        span: Span,
+    },
+    // Stops the program; see `Interpreter::exit_code`.
+    Exit {
+        code: i32,
+        span: Span,
+    },
+    // `assert ok;` or `assert ok, "message";` fails the run unless the
+    // condition is `true`.
+    Assert {
+        condition: Argument,
+        message: Option<String>,
+        span: Span,
+    },
+    // `let x = f(a, 1);` stores the result in `x`; a bare `f(a, 1);` drops it.
+    Call {
+        target: Option<Name>,
+        function: Name,
+        args: Vec<Argument>,
+        span: Span,
+    },
+}
+
+impl ASTNode {
+    pub fn kind(&self) -> &'static str {
+        match self {
+            ASTNode::Let { .. } => "let",
+            ASTNode::Branch { .. } => "branch",
+            ASTNode::Merge { .. } => "merge",
+            ASTNode::Print { .. } => "print",
+            ASTNode::Input { .. } => "input",
+            ASTNode::ListPush { .. } => "listpush",
+            ASTNode::ListPushFront { .. } => "listpushfront",
+            ASTNode::ListPopFront { .. } => "listpopfront",
+            ASTNode::ListPopBack { .. } => "listpopback",
+            ASTNode::SetInsert { .. } => "setinsert",
+            ASTNode::Exit { .. } => "exit",
+            ASTNode::Assert { .. } => "assert",
+            ASTNode::Call { .. } => "call",
+        }
+    }
+    pub fn span(&self) -> Span {
+        match self {
+            ASTNode::Let { span, .. }
+            | ASTNode::Branch { span, .. }
+            | ASTNode::Merge { span, .. }
+            | ASTNode::Print { span, .. }
+            | ASTNode::Input { span, .. }
+            | ASTNode::ListPush { span, .. }
+            | ASTNode::ListPushFront { span, .. }
+            | ASTNode::ListPopFront { span, .. }
+            | ASTNode::ListPopBack { span, .. }
+            | ASTNode::SetInsert { span, .. }
+            | ASTNode::Exit { span, .. }
+            | ASTNode::Assert { span, .. }
+            | ASTNode::Call { span, .. } => *span,
+        }
+    }
+    // The variable the statement reads or writes, if it names one.
+    pub fn variable(&self) -> Option<&str> {
+        match self {
+            ASTNode::Let { name, .. } => Some(name),
+            ASTNode::Branch { variable, .. }
+            | ASTNode::Merge { variable, .. }
+            | ASTNode::Input { variable, .. }
+            | ASTNode::ListPush { variable, .. }
+            | ASTNode::ListPushFront { variable, .. }
+            | ASTNode::ListPopFront { variable, .. }
+            | ASTNode::ListPopBack { variable, .. }
+            | ASTNode::SetInsert { variable, .. }
+            | ASTNode::Print {
+                target: PrintTarget::Variable(variable),
+                ..
+            }
+            | ASTNode::Assert {
+                condition: Argument::Variable(variable),
+                ..
+            } => Some(variable),
+            ASTNode::Call { target, .. } => target.as_deref(),
+            ASTNode::Print { .. } | ASTNode::Exit { .. } | ASTNode::Assert { .. } => None,
+        }
+    }
+    pub(crate) fn span_mut(&mut self) -> &mut Span {
+        match self {
+            ASTNode::Let { span, .. }
+            | ASTNode::Branch { span, .. }
+            | ASTNode::Merge { span, .. }
+            | ASTNode::Print { span, .. }
+            | ASTNode::Input { span, .. }
+            | ASTNode::ListPush { span, .. }
+            | ASTNode::ListPushFront { span, .. }
+            | ASTNode::ListPopFront { span, .. }
+            | ASTNode::ListPopBack { span, .. }
+            | ASTNode::SetInsert { span, .. }
+            | ASTNode::Exit { span, .. }
+            | ASTNode::Assert { span, .. }
+            | ASTNode::Call { span, .. } => span,
+        }
+    }
+}
+
+// ===== Visitor =====
+// Read-only traversal. Override the `visit_*` hooks you care about and call the
+// matching `walk_*` function to keep descending into children.
+pub trait Visitor {
+    fn visit_node(&mut self, node: &ASTNode) {
+        walk_node(self, node);
+    }
+    fn visit_print_target(&mut self, target: &PrintTarget) {
+        walk_print_target(self, target);
+    }
+    fn visit_argument(&mut self, argument: &Argument) {
+        walk_argument(self, argument);
+    }
+    fn visit_variable(&mut self, _name: &str) {}
+    fn visit_value(&mut self, _value: &Value) {}
+}
+
+pub fn walk_ast<V: Visitor + ?Sized>(visitor: &mut V, ast: &[ASTNode]) {
+    for node in ast {
+        visitor.visit_node(node);
+    }
+}
+
+pub fn walk_node<V: Visitor + ?Sized>(visitor: &mut V, node: &ASTNode) {
+    match node {
+        ASTNode::Let { name, value, .. } => {
+            visitor.visit_variable(name);
+            visitor.visit_value(value);
+        }
+        ASTNode::Branch { variable, body, .. } => {
+            visitor.visit_variable(variable);
+            walk_ast(visitor, body);
+        }
+        ASTNode::Merge { variable, .. } => visitor.visit_variable(variable),
+        ASTNode::Print { target, .. } => visitor.visit_print_target(target),
+        ASTNode::Input { variable, .. } => visitor.visit_variable(variable),
+        ASTNode::ListPush {
+            variable, value, ..
+        }
+        | ASTNode::ListPushFront {
+            variable, value, ..
+        }
+        | ASTNode::SetInsert {
+            variable, value, ..
+        } => {
+            visitor.visit_variable(variable);
+            visitor.visit_value(value);
+        }
+        ASTNode::ListPopFront {
+            variable, target, ..
+        }
+        | ASTNode::ListPopBack {
+            variable, target, ..
+        } => {
+            visitor.visit_variable(variable);
+            visitor.visit_variable(target);
+        }
+        ASTNode::Exit { .. } => {}
+        ASTNode::Assert { condition, .. } => visitor.visit_argument(condition),
+        ASTNode::Call { target, args, .. } => {
+            if let Some(target) = target {
+                visitor.visit_variable(target);
+            }
+            for arg in args {
+                visitor.visit_argument(arg);
+            }
+        }
+    }
+}
+
+pub fn walk_argument<V: Visitor + ?Sized>(visitor: &mut V, argument: &Argument) {
+    match argument {
+        Argument::Variable(name) => visitor.visit_variable(name),
+        Argument::Value(value) => visitor.visit_value(value),
+    }
+}
+
+pub fn walk_print_target<V: Visitor + ?Sized>(visitor: &mut V, target: &PrintTarget) {
+    match target {
+        PrintTarget::Variable(name) => visitor.visit_variable(name),
+        PrintTarget::Value(value) => visitor.visit_value(value),
+    }
+}
+
+// Mutable counterpart of `Visitor`, for rewriting passes (renaming, constant folding, ...).
+pub trait VisitorMut {
+    fn visit_node_mut(&mut self, node: &mut ASTNode) {
+        walk_node_mut(self, node);
+    }
+    fn visit_print_target_mut(&mut self, target: &mut PrintTarget) {
+        walk_print_target_mut(self, target);
+    }
+    fn visit_argument_mut(&mut self, argument: &mut Argument) {
+        walk_argument_mut(self, argument);
+    }
+    fn visit_variable_mut(&mut self, _name: &mut Name) {}
+    fn visit_value_mut(&mut self, _value: &mut Value) {}
+}
+
+pub fn walk_ast_mut<V: VisitorMut + ?Sized>(visitor: &mut V, ast: &mut [ASTNode]) {
+    for node in ast {
+        visitor.visit_node_mut(node);
+    }
+}
+
+pub fn walk_node_mut<V: VisitorMut + ?Sized>(visitor: &mut V, node: &mut ASTNode) {
+    match node {
+        ASTNode::Let { name, value, .. } => {
+            visitor.visit_variable_mut(name);
+            visitor.visit_value_mut(value);
+        }
+        ASTNode::Branch { variable, body, .. } => {
+            visitor.visit_variable_mut(variable);
+            walk_ast_mut(visitor, body);
+        }
+        ASTNode::Merge { variable, .. } => visitor.visit_variable_mut(variable),
+        ASTNode::Print { target, .. } => visitor.visit_print_target_mut(target),
+        ASTNode::Input { variable, .. } => visitor.visit_variable_mut(variable),
+        ASTNode::ListPush {
+            variable, value, ..
+        }
+        | ASTNode::ListPushFront {
+            variable, value, ..
+        }
+        | ASTNode::SetInsert {
+            variable, value, ..
+        } => {
+            visitor.visit_variable_mut(variable);
+            visitor.visit_value_mut(value);
+        }
+        ASTNode::ListPopFront {
+            variable, target, ..
+        }
+        | ASTNode::ListPopBack {
+            variable, target, ..
+        } => {
+            visitor.visit_variable_mut(variable);
+            visitor.visit_variable_mut(target);
+        }
+        ASTNode::Exit { .. } => {}
+        ASTNode::Assert { condition, .. } => visitor.visit_argument_mut(condition),
+        ASTNode::Call { target, args, .. } => {
+            if let Some(target) = target {
+                visitor.visit_variable_mut(target);
+            }
+            for arg in args {
+                visitor.visit_argument_mut(arg);
+            }
+        }
+    }
+}
+
+pub fn walk_argument_mut<V: VisitorMut + ?Sized>(visitor: &mut V, argument: &mut Argument) {
+    match argument {
+        Argument::Variable(name) => visitor.visit_variable_mut(name),
+        Argument::Value(value) => visitor.visit_value_mut(value),
+    }
+}
+
+pub fn walk_print_target_mut<V: VisitorMut + ?Sized>(visitor: &mut V, target: &mut PrintTarget) {
+    match target {
+        PrintTarget::Variable(name) => visitor.visit_variable_mut(name),
+        PrintTarget::Value(value) => visitor.visit_value_mut(value),
+    }
+}
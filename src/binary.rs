@@ -0,0 +1,706 @@
+use crate::ast::{ASTNode, Argument, InputType, Name, PrintTarget};
+use crate::lexer::{Span, is_name};
+use crate::value::{Atom, Float, IntRange, Shared, Value, ValueList, ValueSet};
+use crate::vm::{Branch, Interpreter, Program, World};
+use indexmap::IndexMap;
+use std::collections::{HashMap, HashSet};
+
+// ===== Binary encoding =====
+// A compact form of compiled programs, worlds and interpreter state: caches
+// skip lexing and parsing of sources that have not changed, and hosts can
+// suspend a run and resume it elsewhere. Integers are LEB128 varints
+// (zigzagged when signed), strings a byte length and UTF-8. Every encoding
+// starts with `MAGIC`, the `FORMAT` it was written in and what it holds.
+// Anything from another format version, of another kind, or cut short
+// decodes to `None`; bump `FORMAT` whenever the layout changes.
+const MAGIC: &[u8; 4] = b"SNTB";
+const FORMAT: u8 = 2;
+
+// How deep values, branch bodies and nested branches may go, both ways: a
+// crafted input cannot run the decoder out of stack.
+const MAX_DEPTH: usize = 256;
+
+// Counts one more level of nesting; `None` past `MAX_DEPTH`. Callers step
+// back out with `depth -= 1` only on success, as a failure ends the whole
+// encoding.
+fn enter(depth: &mut usize) -> Option<()> {
+    *depth += 1;
+    (*depth <= MAX_DEPTH).then_some(())
+}
+
+const PROGRAM: u8 = b'P';
+const WORLD: u8 = b'W';
+const STATE: u8 = b'S';
+
+// Each `to_bytes` is `None` when a value is a host object, which has no
+// byte form, or when something nests deeper than `MAX_DEPTH`. Decoding a
+// world or a checkpoint is `None` when it holds an atom that no program in
+// this process has made yet: atoms are never freed, so only programs may add
+// them. Compile or decode the program first.
+impl Program {
+    pub fn to_bytes(&self) -> Option<Vec<u8>> {
+        let mut encoder = Encoder::new(PROGRAM);
+        encoder.nodes(self.ast())?;
+        Some(encoder.out)
+    }
+    // Names and strings come back shared, as `compile` leaves them.
+    pub fn from_bytes(bytes: &[u8]) -> Option<Program> {
+        let mut decoder = Decoder::new(bytes, PROGRAM)?;
+        let ast = decoder.nodes()?;
+        decoder.finish(Program::from_ast(ast))
+    }
+}
+
+// Names, values and generations slot by slot, so iteration order and the
+// generations of names without a value survive.
+impl World {
+    pub fn to_bytes(&self) -> Option<Vec<u8>> {
+        let mut encoder = Encoder::new(WORLD);
+        encoder.world(self)?;
+        Some(encoder.out)
+    }
+    pub fn from_bytes(bytes: &[u8]) -> Option<World> {
+        let mut decoder = Decoder::new(bytes, WORLD)?;
+        let world = decoder.world()?;
+        decoder.finish(world)
+    }
+}
+
+impl Interpreter {
+    // The world and the branches closed but not merged yet: everything a
+    // suspended script needs to carry on with its next statement. Hooks,
+    // observers, limits and I/O belong to the host and are not included.
+    pub fn checkpoint(&self) -> Option<Vec<u8>> {
+        let mut encoder = Encoder::new(STATE);
+        encoder.world(self.world())?;
+        encoder.uint(self.branches.len() as u64);
+        for branch in self.branches.values() {
+            encoder.branch(branch)?;
+        }
+        Some(encoder.out)
+    }
+    // Replaces the world and pending branches with a `checkpoint`'s. `None`,
+    // leaving the interpreter as it was, when the bytes are not one.
+    pub fn resume_from(&mut self, bytes: &[u8]) -> Option<()> {
+        let mut decoder = Decoder::new(bytes, STATE)?;
+        let world = decoder.world()?;
+        let n = decoder.len()?;
+        let mut branches = HashMap::with_capacity(n);
+        for _ in 0..n {
+            let branch = decoder.branch()?;
+            branches.insert(branch.variable.clone(), branch);
+        }
+        decoder.finish(())?;
+        *self.world_mut() = world;
+        self.branches = branches;
+        Some(())
+    }
+}
+
+struct Encoder {
+    out: Vec<u8>,
+    depth: usize,
+}
+
+impl Encoder {
+    fn new(kind: u8) -> Self {
+        let mut out = MAGIC.to_vec();
+        out.extend_from_slice(&[FORMAT, kind]);
+        Encoder { out, depth: 0 }
+    }
+    fn uint(&mut self, mut n: u64) {
+        while n >= 0x80 {
+            self.out.push(n as u8 | 0x80);
+            n >>= 7;
+        }
+        self.out.push(n as u8);
+    }
+    fn int(&mut self, n: i64) {
+        self.uint(((n << 1) ^ (n >> 63)) as u64);
+    }
+    fn str(&mut self, s: &str) {
+        self.uint(s.len() as u64);
+        self.out.extend_from_slice(s.as_bytes());
+    }
+    fn span(&mut self, span: Span) {
+        self.uint(span.start as u64);
+        self.uint(span.end as u64);
+    }
+    fn option_str(&mut self, s: Option<&str>) {
+        match s {
+            Some(s) => {
+                self.out.push(1);
+                self.str(s);
+            }
+            None => self.out.push(0),
+        }
+    }
+
+    fn value(&mut self, value: &Value) -> Option<()> {
+        enter(&mut self.depth)?;
+        match value {
+            Value::Nil => self.out.push(0),
+            Value::Int(i) => {
+                self.out.push(1);
+                self.int(*i);
+            }
+            Value::Float(f) => {
+                self.out.push(2);
+                self.out.extend_from_slice(&f.0.to_bits().to_le_bytes());
+            }
+            #[cfg(feature = "decimal")]
+            Value::Decimal(d) => {
+                self.out.push(3);
+                self.out.extend_from_slice(&d.serialize());
+            }
+            Value::Bool(b) => self.out.push(if *b { 5 } else { 4 }),
+            Value::Char(c) => {
+                self.out.push(6);
+                self.uint(*c as u64);
+            }
+            Value::Str(s) => {
+                self.out.push(7);
+                self.str(s);
+            }
+            Value::Atom(a) => {
+                self.out.push(8);
+                self.str(a.name());
+            }
+            Value::List(l) => {
+                self.out.push(9);
+                self.values(l.len(), l.iter())?;
+            }
+            Value::Range(r) => {
+                self.out.push(10);
+                self.int(r.start());
+                self.int(r.step());
+                self.uint(r.len());
+            }
+            Value::IntArray(a) => {
+                self.out.push(11);
+                self.uint(a.len() as u64);
+                for i in a.iter() {
+                    self.int(*i);
+                }
+            }
+            Value::FloatArray(a) => {
+                self.out.push(12);
+                self.uint(a.len() as u64);
+                for f in a.iter() {
+                    self.out.extend_from_slice(&f.0.to_bits().to_le_bytes());
+                }
+            }
+            Value::Set(s) => {
+                self.out.push(13);
+                self.values(s.len(), s.iter())?;
+            }
+            Value::Map(m) => {
+                self.out.push(14);
+                self.uint(m.len() as u64);
+                for (key, value) in m.iter() {
+                    self.str(key);
+                    self.value(value)?;
+                }
+            }
+            Value::Extern(_) => return None,
+        }
+        self.depth -= 1;
+        Some(())
+    }
+    fn values<'a>(&mut self, len: usize, values: impl Iterator<Item = &'a Value>) -> Option<()> {
+        self.uint(len as u64);
+        for value in values {
+            self.value(value)?;
+        }
+        Some(())
+    }
+    fn argument(&mut self, argument: &Argument) -> Option<()> {
+        match argument {
+            Argument::Variable(name) => {
+                self.out.push(0);
+                self.str(name);
+            }
+            Argument::Value(value) => {
+                self.out.push(1);
+                self.value(value)?;
+            }
+        }
+        Some(())
+    }
+
+    fn option_value(&mut self, value: Option<&Value>) -> Option<()> {
+        match value {
+            Some(value) => {
+                self.out.push(1);
+                self.value(value)
+            }
+            None => {
+                self.out.push(0);
+                Some(())
+            }
+        }
+    }
+    fn world(&mut self, world: &World) -> Option<()> {
+        let slots = world.slots();
+        self.uint(slots.len() as u64);
+        for (name, value, generation) in slots {
+            self.str(name);
+            self.option_value(value)?;
+            self.uint(generation as u64);
+        }
+        Some(())
+    }
+    fn branch(&mut self, branch: &Branch) -> Option<()> {
+        enter(&mut self.depth)?;
+        self.str(&branch.variable);
+        self.option_value(branch.delta.as_ref())?;
+        self.uint(branch.generation as u64);
+        self.uint(branch.nested.len() as u64);
+        for nested in &branch.nested {
+            self.branch(nested)?;
+        }
+        self.depth -= 1;
+        Some(())
+    }
+
+    fn nodes(&mut self, nodes: &[ASTNode]) -> Option<()> {
+        self.uint(nodes.len() as u64);
+        for node in nodes {
+            self.node(node)?;
+        }
+        Some(())
+    }
+    // Tags follow the order of `ASTNode`'s variants.
+    fn node(&mut self, node: &ASTNode) -> Option<()> {
+        enter(&mut self.depth)?;
+        match node {
+            ASTNode::Let { name, value, .. } => {
+                self.out.push(0);
+                self.str(name);
+                self.value(value)?;
+            }
+            ASTNode::Branch { variable, body, .. } => {
+                self.out.push(1);
+                self.str(variable);
+                self.nodes(body)?;
+            }
+            ASTNode::Merge { variable, .. } => {
+                self.out.push(2);
+                self.str(variable);
+            }
+            ASTNode::Print { target, .. } => {
+                self.out.push(3);
+                // Laid out like an `Argument`.
+                match target {
+                    PrintTarget::Variable(name) => {
+                        self.out.push(0);
+                        self.str(name);
+                    }
+                    PrintTarget::Value(value) => {
+                        self.out.push(1);
+                        self.value(value)?;
+                    }
+                }
+            }
+            ASTNode::Input {
+                prompt,
+                input_type,
+                variable,
+                ..
+            } => {
+                self.out.push(4);
+                self.option_str(prompt.as_deref());
+                self.out.push(match input_type {
+                    InputType::Str => 0,
+                    InputType::Int => 1,
+                    InputType::Float => 2,
+                });
+                self.str(variable);
+            }
+            ASTNode::ListPush {
+                variable, value, ..
+            }
+            | ASTNode::ListPushFront {
+                variable, value, ..
+            }
+            | ASTNode::SetInsert {
+                variable, value, ..
+            } => {
+                self.out.push(match node {
+                    ASTNode::ListPush { .. } => 5,
+                    ASTNode::ListPushFront { .. } => 6,
+                    _ => 9,
+                });
+                self.str(variable);
+                self.value(value)?;
+            }
+            ASTNode::ListPopFront {
+                variable, target, ..
+            }
+            | ASTNode::ListPopBack {
+                variable, target, ..
+            } => {
+                self.out.push(match node {
+                    ASTNode::ListPopFront { .. } => 7,
+                    _ => 8,
+                });
+                self.str(variable);
+                self.str(target);
+            }
+            ASTNode::Exit { code, .. } => {
+                self.out.push(10);
+                self.int(*code as i64);
+            }
+            ASTNode::Assert {
+                condition, message, ..
+            } => {
+                self.out.push(11);
+                self.argument(condition)?;
+                self.option_str(message.as_deref());
+            }
+            ASTNode::Call {
+                target,
+                function,
+                args,
+                ..
+            } => {
+                self.out.push(12);
+                self.option_str(target.as_deref());
+                self.str(function);
+                self.uint(args.len() as u64);
+                for arg in args {
+                    self.argument(arg)?;
+                }
+            }
+        }
+        self.span(node.span());
+        self.depth -= 1;
+        Some(())
+    }
+}
+
+struct Decoder<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+    depth: usize,
+    // Shared as `compile` shares them: one allocation per distinct name
+    // and per distinct string.
+    names: HashSet<Name>,
+    strings: HashMap<&'a str, Shared<String>>,
+    // Only a program may bring new atoms, as compiling its source would;
+    // see `Atom`.
+    new_atoms: bool,
+}
+
+impl<'a> Decoder<'a> {
+    fn new(bytes: &'a [u8], kind: u8) -> Option<Self> {
+        let bytes = bytes.strip_prefix(MAGIC)?.strip_prefix(&[FORMAT, kind])?;
+        Some(Decoder {
+            bytes,
+            pos: 0,
+            depth: 0,
+            names: HashSet::new(),
+            strings: HashMap::new(),
+            new_atoms: kind == PROGRAM,
+        })
+    }
+    // `value`, if nothing is left over.
+    fn finish<T>(self, value: T) -> Option<T> {
+        (self.pos == self.bytes.len()).then_some(value)
+    }
+    fn take(&mut self, n: usize) -> Option<&'a [u8]> {
+        let bytes = self.bytes.get(self.pos..self.pos.checked_add(n)?)?;
+        self.pos += n;
+        Some(bytes)
+    }
+    fn byte(&mut self) -> Option<u8> {
+        Some(self.take(1)?[0])
+    }
+    fn uint(&mut self) -> Option<u64> {
+        let mut n = 0u64;
+        for shift in (0..64).step_by(7) {
+            let byte = self.byte()?;
+            n |= ((byte & 0x7f) as u64).checked_shl(shift)?;
+            if byte < 0x80 {
+                return Some(n);
+            }
+        }
+        None
+    }
+    fn int(&mut self) -> Option<i64> {
+        let n = self.uint()?;
+        Some((n >> 1) as i64 ^ -((n & 1) as i64))
+    }
+    fn usize(&mut self) -> Option<usize> {
+        usize::try_from(self.uint()?).ok()
+    }
+    // A length, which can be no more than the bytes left since every item
+    // takes at least one; keeps a corrupt count from reserving gigabytes.
+    fn len(&mut self) -> Option<usize> {
+        self.usize().filter(|&n| n <= self.bytes.len() - self.pos)
+    }
+    fn str(&mut self) -> Option<&'a str> {
+        let n = self.usize()?;
+        std::str::from_utf8(self.take(n)?).ok()
+    }
+    fn name(&mut self) -> Option<Name> {
+        let s = self.str()?;
+        if let Some(name) = self.names.get(s) {
+            return Some(name.clone());
+        }
+        let name: Name = s.into();
+        self.names.insert(name.clone());
+        Some(name)
+    }
+    fn option_str(&mut self) -> Option<Option<String>> {
+        match self.byte()? {
+            0 => Some(None),
+            1 => Some(Some(self.str()?.to_string())),
+            _ => None,
+        }
+    }
+    // Canonical, as every float in a value is; see `Float::canonical`.
+    fn float(&mut self) -> Option<Float> {
+        let bytes = self.take(8)?.try_into().ok()?;
+        Some(Float::canonical(f64::from_bits(u64::from_le_bytes(bytes))))
+    }
+    fn atom(&mut self) -> Option<Atom> {
+        let name = self.str()?;
+        if !is_name(name) {
+            return None;
+        }
+        if self.new_atoms {
+            Some(Atom::new(name))
+        } else {
+            Atom::existing(name)
+        }
+    }
+    // Only what `Decimal::serialize` writes: a scale of at most 28 and no
+    // flag bits besides the sign.
+    #[cfg(feature = "decimal")]
+    fn decimal(&mut self) -> Option<rust_decimal::Decimal> {
+        let bytes: [u8; 16] = self.take(16)?.try_into().ok()?;
+        if bytes[0] != 0 || bytes[1] != 0 || bytes[2] > 28 || bytes[3] & 0x7f != 0 {
+            return None;
+        }
+        Some(rust_decimal::Decimal::deserialize(bytes))
+    }
+    fn span(&mut self) -> Option<Span> {
+        Some(Span {
+            start: self.usize()?,
+            end: self.usize()?,
+        })
+    }
+
+    fn value(&mut self) -> Option<Value> {
+        enter(&mut self.depth)?;
+        let value = match self.byte()? {
+            0 => Value::Nil,
+            1 => Value::Int(self.int()?),
+            2 => Value::Float(self.float()?),
+            #[cfg(feature = "decimal")]
+            3 => Value::Decimal(self.decimal()?),
+            4 => Value::Bool(false),
+            5 => Value::Bool(true),
+            6 => Value::Char(char::from_u32(u32::try_from(self.uint()?).ok()?)?),
+            7 => {
+                let s = self.str()?;
+                let shared = self
+                    .strings
+                    .entry(s)
+                    .or_insert_with(|| Shared::new(s.to_string()));
+                Value::Str(shared.clone())
+            }
+            8 => Value::Atom(self.atom()?),
+            9 => Value::List(Shared::new(self.values()?)),
+            10 => {
+                let (start, step, len) = (self.int()?, self.int()?, self.uint()?);
+                Value::from(IntRange::from_parts(start, step, len)?)
+            }
+            11 => {
+                let n = self.len()?;
+                let mut a = Vec::with_capacity(n);
+                for _ in 0..n {
+                    a.push(self.int()?);
+                }
+                Value::IntArray(Shared::new(a))
+            }
+            12 => {
+                let n = self.len()?;
+                let mut a = Vec::with_capacity(n);
+                for _ in 0..n {
+                    a.push(self.float()?);
+                }
+                Value::FloatArray(Shared::new(a))
+            }
+            13 => {
+                let n = self.len()?;
+                let mut s = ValueSet::new();
+                for _ in 0..n {
+                    s.insert(self.value()?);
+                }
+                Value::Set(Shared::new(s))
+            }
+            14 => {
+                let n = self.len()?;
+                let mut m = IndexMap::with_capacity(n);
+                for _ in 0..n {
+                    let key = self.str()?.to_string();
+                    m.insert(key, self.value()?);
+                }
+                Value::from(m)
+            }
+            _ => return None,
+        };
+        self.depth -= 1;
+        Some(value)
+    }
+    fn values(&mut self) -> Option<ValueList> {
+        let n = self.len()?;
+        let mut values = ValueList::with_capacity(n);
+        for _ in 0..n {
+            values.push(self.value()?);
+        }
+        Some(values)
+    }
+    fn option_value(&mut self) -> Option<Option<Value>> {
+        match self.byte()? {
+            0 => Some(None),
+            1 => Some(Some(self.value()?)),
+            _ => None,
+        }
+    }
+    fn world(&mut self) -> Option<World> {
+        let n = self.len()?;
+        let mut world = World::new();
+        for _ in 0..n {
+            let name = self.str()?;
+            let value = self.option_value()?;
+            world.set_slot(name, value, self.usize()?)?;
+        }
+        Some(world)
+    }
+    fn branch(&mut self) -> Option<Branch> {
+        enter(&mut self.depth)?;
+        let variable = self.str()?;
+        let delta = self.option_value()?;
+        let mut branch = Branch::new(variable, delta, self.usize()?);
+        let n = self.len()?;
+        for _ in 0..n {
+            branch.nested.push(self.branch()?);
+        }
+        self.depth -= 1;
+        Some(branch)
+    }
+    fn argument(&mut self) -> Option<Argument> {
+        match self.byte()? {
+            0 => Some(Argument::Variable(self.name()?)),
+            1 => Some(Argument::Value(self.value()?)),
+            _ => None,
+        }
+    }
+
+    fn nodes(&mut self) -> Option<Vec<ASTNode>> {
+        let n = self.len()?;
+        let mut nodes = Vec::with_capacity(n);
+        for _ in 0..n {
+            nodes.push(self.node()?);
+        }
+        Some(nodes)
+    }
+    fn node(&mut self) -> Option<ASTNode> {
+        enter(&mut self.depth)?;
+        let span = Span::default();
+        let mut node = match self.byte()? {
+            0 => ASTNode::Let {
+                name: self.name()?,
+                value: self.value()?,
+                span,
+            },
+            1 => ASTNode::Branch {
+                variable: self.name()?,
+                body: self.nodes()?,
+                span,
+            },
+            2 => ASTNode::Merge {
+                variable: self.name()?,
+                span,
+            },
+            3 => ASTNode::Print {
+                target: match self.argument()? {
+                    Argument::Variable(name) => PrintTarget::Variable(name),
+                    Argument::Value(value) => PrintTarget::Value(value),
+                },
+                span,
+            },
+            4 => ASTNode::Input {
+                prompt: self.option_str()?,
+                input_type: match self.byte()? {
+                    0 => InputType::Str,
+                    1 => InputType::Int,
+                    2 => InputType::Float,
+                    _ => return None,
+                },
+                variable: self.name()?,
+                span,
+            },
+            5 => ASTNode::ListPush {
+                variable: self.name()?,
+                value: self.value()?,
+                span,
+            },
+            6 => ASTNode::ListPushFront {
+                variable: self.name()?,
+                value: self.value()?,
+                span,
+            },
+            7 => ASTNode::ListPopFront {
+                variable: self.name()?,
+                target: self.name()?,
+                span,
+            },
+            8 => ASTNode::ListPopBack {
+                variable: self.name()?,
+                target: self.name()?,
+                span,
+            },
+            9 => ASTNode::SetInsert {
+                variable: self.name()?,
+                value: self.value()?,
+                span,
+            },
+            10 => ASTNode::Exit {
+                code: i32::try_from(self.int()?).ok()?,
+                span,
+            },
+            11 => ASTNode::Assert {
+                condition: self.argument()?,
+                message: self.option_str()?,
+                span,
+            },
+            12 => {
+                let target = match self.byte()? {
+                    0 => None,
+                    1 => Some(self.name()?),
+                    _ => return None,
+                };
+                let function = self.name()?;
+                let n = self.len()?;
+                let mut args = Vec::with_capacity(n);
+                for _ in 0..n {
+                    args.push(self.argument()?);
+                }
+                ASTNode::Call {
+                    target,
+                    function,
+                    args,
+                    span,
+                }
+            }
+            _ => return None,
+        };
+        *node.span_mut() = self.span()?;
+        self.depth -= 1;
+        Some(node)
+    }
+}
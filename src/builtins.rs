@@ -0,0 +1,1135 @@
+use crate::date;
+use crate::value::{Float, IntRange, Overflow, Shared, Value};
+use crate::vm::{Interpreter, RuntimeError, RuntimeErrorKind};
+use indexmap::IndexMap;
+use std::hash::{BuildHasher, Hasher, RandomState};
+
+// ===== Builtins =====
+// Functions scripts can call: `let x = name(args);` or `name(args);`.
+pub const NAMES: &[&str] = &[
+    "arradd",
+    "arrmul",
+    "b64decode",
+    "b64encode",
+    "charat",
+    "chars",
+    "chr",
+    "csvread",
+    "csvwrite",
+    "dateadd",
+    "datediff",
+    "datefmt",
+    "dateparse",
+    #[cfg(feature = "decimal")]
+    "decadd",
+    #[cfg(feature = "decimal")]
+    "decdiv",
+    #[cfg(feature = "decimal")]
+    "decimal",
+    #[cfg(feature = "decimal")]
+    "decmul",
+    #[cfg(feature = "decimal")]
+    "decround",
+    #[cfg(feature = "decimal")]
+    "decsub",
+    "deepcopy",
+    "eq",
+    #[cfg(all(feature = "process", not(target_arch = "wasm32")))]
+    "exec",
+    "fill",
+    "floatarray",
+    "format",
+    "getenv",
+    #[cfg(all(feature = "net", not(target_arch = "wasm32")))]
+    "httpget",
+    "intarray",
+    "jsonparse",
+    "jsonstr",
+    "len",
+    "list",
+    #[cfg(feature = "hashing")]
+    "md5",
+    #[cfg(not(target_arch = "wasm32"))]
+    "now",
+    "ord",
+    "rand",
+    "randint",
+    "randseed",
+    "range",
+    #[cfg(feature = "hashing")]
+    "sha256",
+    "sort",
+    "sum",
+    "typeof",
+    #[cfg(feature = "uuid")]
+    "uuid",
+];
+
+pub(crate) fn call(
+    interp: &mut Interpreter,
+    name: &str,
+    args: &[Value],
+) -> Result<Option<Value>, RuntimeError> {
+    match name {
+        // Strings count chars, not bytes.
+        "len" => {
+            arity(name, args, 1)?;
+            let len = match &args[0] {
+                Value::Str(s) => s.chars().count() as u64,
+                Value::List(l) => l.len() as u64,
+                // Only `range(i64::MIN, i64::MAX)` and its like overflow.
+                Value::Range(r) => r.len(),
+                Value::IntArray(a) => a.len() as u64,
+                Value::FloatArray(a) => a.len() as u64,
+                Value::Set(s) => s.len() as u64,
+                Value::Map(m) => m.len() as u64,
+                other => {
+                    return Err(argument_error(format!(
+                        "`len` needs a string, list, range, set or map, got {}",
+                        other.describe()
+                    )));
+                }
+            };
+            integer(interp, name, len as i128)
+        }
+        // `range(end)`, `range(start, end)` or `range(start, end, step)`:
+        // lazy, like Python's. `listpopfront` and `listpopback` take from
+        // either end without building the list.
+        "range" => {
+            let (start, end, step) = match args.len() {
+                1 => (0, int(name, args, 0)?, 1),
+                2 => (int(name, args, 0)?, int(name, args, 1)?, 1),
+                _ => {
+                    arity(name, args, 3)?;
+                    (
+                        int(name, args, 0)?,
+                        int(name, args, 1)?,
+                        int(name, args, 2)?,
+                    )
+                }
+            };
+            let range = IntRange::new(start, end, step)
+                .ok_or_else(|| argument_error("`range` step must not be 0".to_string()))?;
+            Ok(Some(Value::from(range)))
+        }
+        // A list of the elements of a range, an array, a set (sorted) or a
+        // list.
+        "list" => {
+            arity(name, args, 1)?;
+            let items: Vec<Value> = match &args[0] {
+                Value::List(l) => return Ok(Some(Value::List(l.clone()))),
+                Value::Range(r) => {
+                    collection_size(name, r.len())?;
+                    r.iter().map(Value::Int).collect()
+                }
+                Value::IntArray(a) => a.iter().map(|&i| Value::Int(i)).collect(),
+                Value::FloatArray(a) => a.iter().map(|&f| Value::Float(f)).collect(),
+                Value::Set(s) => {
+                    let mut items: Vec<Value> = s.iter().cloned().collect();
+                    items.sort();
+                    items
+                }
+                other => {
+                    return Err(argument_error(format!(
+                        "`list` needs a list, range, array or set, got {}",
+                        other.describe()
+                    )));
+                }
+            };
+            Ok(Some(Value::from(items)))
+        }
+        // Structural equality; floats compare as `InterpreterBuilder::float_eq`
+        // says.
+        "eq" => {
+            arity(name, args, 2)?;
+            Ok(Some(Value::Bool(args[0].equals(&args[1], interp.float_eq))))
+        }
+        // ===== Typed arrays =====
+        // `intarray(n)` is n zeros and `intarray(n, fill)` n copies of fill;
+        // `intarray(items)` takes a list, range or array of integers.
+        // `floatarray` is the same for floats, and also accepts integers.
+        "intarray" | "floatarray" => {
+            let float = name == "floatarray";
+            if args.len() != 2 {
+                arity(name, args, 1)?;
+            }
+            let array = match (&args[0], args.get(1)) {
+                (Value::Int(n), fill) => {
+                    let n = u64::try_from(*n).map_err(|_| {
+                        argument_error(format!("`{}` length must not be negative", name))
+                    })?;
+                    collection_size(name, n)?;
+                    let fill = fill.unwrap_or(&Value::Int(0));
+                    if float {
+                        Value::FloatArray(Shared::new(vec![
+                            Float::canonical(element_float(
+                                name, fill
+                            )?);
+                            n as usize
+                        ]))
+                    } else {
+                        Value::IntArray(Shared::new(vec![element_int(name, fill)?; n as usize]))
+                    }
+                }
+                (items, None) => {
+                    let items: Vec<Value> = match items {
+                        Value::List(l) => l.to_vec(),
+                        Value::Range(r) => {
+                            collection_size(name, r.len())?;
+                            r.iter().map(Value::Int).collect()
+                        }
+                        Value::IntArray(a) => a.iter().map(|&i| Value::Int(i)).collect(),
+                        Value::FloatArray(a) => a.iter().map(|&f| Value::Float(f)).collect(),
+                        other => {
+                            return Err(argument_error(format!(
+                                "`{}` needs a length or a list, range or array, got {}",
+                                name,
+                                other.describe()
+                            )));
+                        }
+                    };
+                    if float {
+                        let items = items
+                            .iter()
+                            .map(|v| element_float(name, v).map(Float::canonical));
+                        Value::FloatArray(Shared::new(items.collect::<Result<_, _>>()?))
+                    } else {
+                        let items = items.iter().map(|v| element_int(name, v));
+                        Value::IntArray(Shared::new(items.collect::<Result<_, _>>()?))
+                    }
+                }
+                (other, Some(_)) => {
+                    return Err(argument_error(format!(
+                        "argument 1 of `{}` must be a length when there is a fill value, got {}",
+                        name,
+                        other.describe()
+                    )));
+                }
+            };
+            Ok(Some(array))
+        }
+        // Integer sums are exact, then fitted to an i64 under the overflow
+        // policy. A list sums as an integer unless it holds a float.
+        "sum" => {
+            arity(name, args, 1)?;
+            let floats: Vec<f64> = match &args[0] {
+                Value::IntArray(a) => {
+                    return integer(interp, name, a.iter().map(|&i| i as i128).sum());
+                }
+                Value::Range(r) => {
+                    return integer(interp, name, r.sum());
+                }
+                Value::FloatArray(a) => a.iter().map(|f| f.0).collect(),
+                Value::List(l) if l.iter().all(|v| matches!(v, Value::Int(_))) => {
+                    let sum = l.iter().map(|v| match v {
+                        Value::Int(i) => *i as i128,
+                        _ => 0,
+                    });
+                    return integer(interp, name, sum.sum());
+                }
+                Value::List(l) => l
+                    .iter()
+                    .map(|v| element_float(name, v))
+                    .collect::<Result<_, _>>()?,
+                other => {
+                    return Err(argument_error(format!(
+                        "`sum` needs an array, range or list of numbers, got {}",
+                        other.describe()
+                    )));
+                }
+            };
+            Ok(Some(Value::from(floats.iter().sum::<f64>())))
+        }
+        // A new array with the scalar added to or multiplied into every
+        // element, or with every element replaced by it.
+        "arradd" | "arrmul" | "fill" => {
+            arity(name, args, 2)?;
+            let scalar = &args[1];
+            match &args[0] {
+                Value::IntArray(a) => {
+                    let k = element_int(name, scalar)? as i128;
+                    let exact: Vec<i128> = match name {
+                        "arradd" => a.iter().map(|&i| i as i128 + k).collect(),
+                        "arrmul" => a.iter().map(|&i| i as i128 * k).collect(),
+                        _ => vec![k; a.len()],
+                    };
+                    let fitted: Option<Vec<i64>> =
+                        exact.iter().map(|&n| fit(interp.overflow, n)).collect();
+                    match fitted {
+                        Some(items) => Ok(Some(Value::IntArray(Shared::new(items)))),
+                        None if interp.overflow == Overflow::Float => {
+                            let items = exact.iter().map(|&n| Float::canonical(n as f64));
+                            Ok(Some(Value::FloatArray(Shared::new(items.collect()))))
+                        }
+                        None => Err(overflow_error(interp, name)),
+                    }
+                }
+                Value::FloatArray(a) => {
+                    let k = element_float(name, scalar)?;
+                    let items: Vec<Float> = match name {
+                        "arradd" => a.iter().map(|f| Float::canonical(f.0 + k)).collect(),
+                        "arrmul" => a.iter().map(|f| Float::canonical(f.0 * k)).collect(),
+                        _ => vec![Float::canonical(k); a.len()],
+                    };
+                    Ok(Some(Value::FloatArray(Shared::new(items))))
+                }
+                other => Err(argument_error(format!(
+                    "argument 1 of `{}` must be an intarray or floatarray, got {}",
+                    name,
+                    other.describe()
+                ))),
+            }
+        }
+        // Strings index and iterate by Unicode scalar value, the same unit
+        // `len` counts, so an accented letter written as two code points is
+        // two chars.
+        "chars" => {
+            arity(name, args, 1)?;
+            let chars = str(name, args, 0)?.chars().map(Value::Char).collect();
+            Ok(Some(Value::List(Shared::new(chars))))
+        }
+        "charat" => {
+            arity(name, args, 2)?;
+            let (text, index) = (str(name, args, 0)?, int(name, args, 1)?);
+            usize::try_from(index)
+                .ok()
+                .and_then(|i| text.chars().nth(i))
+                .map(|c| Some(Value::Char(c)))
+                .ok_or_else(|| {
+                    argument_error(format!(
+                        "`charat` index {} is out of range for a string of {} chars",
+                        index,
+                        text.chars().count()
+                    ))
+                })
+        }
+        "ord" => {
+            arity(name, args, 1)?;
+            match &args[0] {
+                Value::Char(c) => Ok(Some(Value::Int(*c as i64))),
+                other => Err(argument_error(format!(
+                    "argument 1 of `ord` must be a char, got {}",
+                    other.describe()
+                ))),
+            }
+        }
+        // Surrogates (U+D800 to U+DFFF) are not scalar values, so they are
+        // rejected along with anything past U+10FFFF.
+        "chr" => {
+            arity(name, args, 1)?;
+            let code = int(name, args, 0)?;
+            u32::try_from(code)
+                .ok()
+                .and_then(char::from_u32)
+                .map(|c| Some(Value::Char(c)))
+                .ok_or_else(|| {
+                    argument_error(format!("`chr`: {} is not a Unicode scalar value", code))
+                })
+        }
+        // A float in [0, 1).
+        "rand" => {
+            arity(name, args, 0)?;
+            Ok(Some(Value::Float(Float(interp.rng.next_f64()))))
+        }
+        // An integer in [lo, hi], both ends included.
+        "randint" => {
+            arity(name, args, 2)?;
+            let (lo, hi) = (int(name, args, 0)?, int(name, args, 1)?);
+            if lo > hi {
+                return Err(argument_error(format!(
+                    "`randint` needs lo <= hi, got {} and {}",
+                    lo, hi
+                )));
+            }
+            let span = (hi as i128 - lo as i128 + 1) as u128;
+            let offset = (interp.rng.next_u64() as u128 * span) >> 64;
+            Ok(Some(Value::Int((lo as i128 + offset as i128) as i64)))
+        }
+        "randseed" => {
+            arity(name, args, 1)?;
+            interp.rng = Rng::new(int(name, args, 0)? as u64);
+            Ok(None)
+        }
+        // Standard alphabet with `=` padding. Bytes are a string's UTF-8 or a
+        // list of integers 0-255.
+        "b64encode" => {
+            arity(name, args, 1)?;
+            let bytes = match &args[0] {
+                Value::Str(s) => s.as_bytes().to_vec(),
+                Value::List(l) => l
+                    .iter()
+                    .map(|b| match b {
+                        Value::Int(b) => u8::try_from(*b).ok(),
+                        _ => None,
+                    })
+                    .collect::<Option<Vec<u8>>>()
+                    .ok_or_else(|| {
+                        argument_error(
+                            "`b64encode` needs a list of integers 0-255 for bytes".to_string(),
+                        )
+                    })?,
+                other => {
+                    return Err(argument_error(format!(
+                        "`b64encode` needs a string or a list of bytes, got {}",
+                        other.describe()
+                    )));
+                }
+            };
+            Ok(Some(Value::from(base64_encode(&bytes))))
+        }
+        // A string when the bytes are UTF-8, otherwise a list of them.
+        "b64decode" => {
+            arity(name, args, 1)?;
+            let bytes = base64_decode(str(name, args, 0)?)
+                .ok_or_else(|| argument_error("`b64decode`: not valid base64".to_string()))?;
+            Ok(Some(match String::from_utf8(bytes) {
+                Ok(text) => Value::from(text),
+                Err(e) => Value::from(
+                    e.into_bytes()
+                        .into_iter()
+                        .map(|b| Value::Int(b as i64))
+                        .collect::<Vec<_>>(),
+                ),
+            }))
+        }
+        // Every field is a string. `csvread(path, true)` takes the first row
+        // as headers and returns a map per remaining row.
+        "csvread" => {
+            if args.len() != 2 {
+                arity(name, args, 1)?;
+            }
+            let path = str(name, args, 0)?;
+            let headers = match args.get(1) {
+                None => false,
+                Some(Value::Bool(b)) => *b,
+                Some(other) => {
+                    return Err(argument_error(format!(
+                        "argument 2 of `csvread` must be a bool, got {}",
+                        other.describe()
+                    )));
+                }
+            };
+            unsandboxed(interp, name)?;
+            let text = std::fs::read_to_string(path).map_err(|e| io_error(path, e))?;
+            let mut rows = csv_parse(&text)
+                .ok_or_else(|| argument_error(format!("`csvread`: unclosed quote in {}", path)))?;
+            if !headers {
+                return Ok(Some(Value::from(
+                    rows.into_iter()
+                        .map(|row| {
+                            Value::from(row.into_iter().map(Value::from).collect::<Vec<_>>())
+                        })
+                        .collect::<Vec<_>>(),
+                )));
+            }
+            let keys = if rows.is_empty() {
+                Vec::new()
+            } else {
+                rows.remove(0)
+            };
+            Ok(Some(Value::from(
+                rows.into_iter()
+                    .map(|row| {
+                        Value::from(
+                            keys.iter()
+                                .cloned()
+                                .zip(row.into_iter().map(Value::from))
+                                .collect::<IndexMap<_, _>>(),
+                        )
+                    })
+                    .collect::<Vec<_>>(),
+            )))
+        }
+        // Rows are lists, or maps written under a header row of their keys
+        // (taken from the first map). Fields are written as `print` shows
+        // them.
+        "csvwrite" => {
+            arity(name, args, 2)?;
+            let path = str(name, args, 0)?;
+            let Value::List(rows) = &args[1] else {
+                return Err(argument_error(format!(
+                    "argument 2 of `csvwrite` must be a list of rows, got {}",
+                    args[1].describe()
+                )));
+            };
+            unsandboxed(interp, name)?;
+            let mut out = String::new();
+            let keys: Option<Vec<String>> = match rows.iter().next() {
+                Some(Value::Map(m)) => Some(m.keys().cloned().collect()),
+                _ => None,
+            };
+            if let Some(keys) = &keys {
+                csv_row(&mut out, keys.iter().map(|k| Value::from(k.as_str())));
+            }
+            for row in rows.iter() {
+                match (row, &keys) {
+                    (Value::List(fields), None) => csv_row(&mut out, fields.iter().cloned()),
+                    (Value::Map(fields), Some(keys)) => csv_row(
+                        &mut out,
+                        keys.iter()
+                            .map(|k| fields.get(k).cloned().unwrap_or(Value::from(""))),
+                    ),
+                    (other, _) => {
+                        return Err(argument_error(format!(
+                            "`csvwrite`: rows must all be lists or all be maps, got {}",
+                            other.describe()
+                        )));
+                    }
+                }
+            }
+            std::fs::write(path, out).map_err(|e| io_error(path, e))?;
+            Ok(None)
+        }
+        // Times are seconds since the Unix epoch, UTC; see `date`.
+        #[cfg(not(target_arch = "wasm32"))]
+        "now" => {
+            arity(name, args, 0)?;
+            let secs = std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .map_or(0, |d| d.as_secs() as i64);
+            Ok(Some(Value::Int(secs)))
+        }
+        "dateparse" => {
+            if args.len() != 2 {
+                arity(name, args, 1)?;
+            }
+            let text = str(name, args, 0)?;
+            let parsed = match args.get(1) {
+                Some(_) => date::parse(text, str(name, args, 1)?),
+                None => date::PARSE_FORMATS
+                    .iter()
+                    .find_map(|fmt| date::parse(text, fmt)),
+            };
+            match parsed {
+                Some(secs) => Ok(Some(Value::Int(secs))),
+                None => Err(argument_error(format!(
+                    "`dateparse`: cannot read {:?} as a date",
+                    text
+                ))),
+            }
+        }
+        "datefmt" => {
+            if args.len() != 2 {
+                arity(name, args, 1)?;
+            }
+            let secs = int(name, args, 0)?;
+            let fmt = match args.get(1) {
+                Some(_) => str(name, args, 1)?,
+                None => date::ISO_8601,
+            };
+            Ok(Some(Value::from(date::format(secs, fmt))))
+        }
+        // The duration is seconds or a string such as "1h30m".
+        "dateadd" => {
+            arity(name, args, 2)?;
+            let secs = int(name, args, 0)?;
+            let duration = match &args[1] {
+                Value::Int(n) => Some(*n),
+                Value::Str(s) => date::parse_duration(s),
+                _ => None,
+            }
+            .ok_or_else(|| {
+                argument_error(format!(
+                    "argument 2 of `dateadd` must be seconds or a duration like \"1h30m\", got {}",
+                    args[1].describe()
+                ))
+            })?;
+            integer(interp, name, secs as i128 + duration as i128)
+        }
+        // Seconds from the second time to the first.
+        "datediff" => {
+            arity(name, args, 2)?;
+            let (a, b) = (int(name, args, 0)?, int(name, args, 1)?);
+            integer(interp, name, a as i128 - b as i128)
+        }
+        // Ints, floats and numeric strings become decimals. Floats convert
+        // by their shortest printed form, so `0.1` is exactly 0.1.
+        #[cfg(feature = "decimal")]
+        "decimal" => {
+            arity(name, args, 1)?;
+            let d = match &args[0] {
+                Value::Str(s) => rust_decimal::Decimal::from_str_exact(s.trim()).ok(),
+                Value::Float(f) => rust_decimal::Decimal::from_str_exact(&f.0.to_string()).ok(),
+                other => decimal_operand(other),
+            };
+            d.map(|d| Some(Value::Decimal(d))).ok_or_else(|| {
+                argument_error(format!("`decimal`: cannot convert {}", args[0].describe()))
+            })
+        }
+        // Exact arithmetic on decimals and ints. Overflow is an error, as is
+        // dividing by zero.
+        #[cfg(feature = "decimal")]
+        "decadd" | "decsub" | "decmul" | "decdiv" => {
+            arity(name, args, 2)?;
+            let (a, b) = (decimal_arg(name, args, 0)?, decimal_arg(name, args, 1)?);
+            if name == "decdiv" && b.is_zero() {
+                return Err(argument_error("`decdiv`: division by zero".to_string()));
+            }
+            let result = match name {
+                "decadd" => a.checked_add(b),
+                "decsub" => a.checked_sub(b),
+                "decmul" => a.checked_mul(b),
+                _ => a.checked_div(b),
+            };
+            result.map(|d| Some(Value::Decimal(d))).ok_or_else(|| {
+                RuntimeError::new(
+                    RuntimeErrorKind::Overflow,
+                    format!("`{}` overflowed the range of a decimal", name),
+                )
+            })
+        }
+        // Banker's rounding to the given number of decimal places.
+        #[cfg(feature = "decimal")]
+        "decround" => {
+            arity(name, args, 2)?;
+            let d = decimal_arg(name, args, 0)?;
+            let places = u32::try_from(int(name, args, 1)?).map_err(|_| {
+                argument_error("`decround` needs a non-negative number of places".to_string())
+            })?;
+            Ok(Some(Value::Decimal(d.round_dp(places))))
+        }
+        // See `Value::deep_copy`.
+        "deepcopy" => {
+            arity(name, args, 1)?;
+            Ok(Some(args[0].deep_copy()))
+        }
+        // `[code, stdout, stderr]`; the code is nil when a signal ended the
+        // process. The command runs directly, not through a shell.
+        #[cfg(all(feature = "process", not(target_arch = "wasm32")))]
+        "exec" => {
+            if args.len() != 2 {
+                arity(name, args, 1)?;
+            }
+            let cmd = str(name, args, 0)?;
+            let argv: Vec<String> = match args.get(1) {
+                None => Vec::new(),
+                Some(Value::List(l)) => l.iter().map(Value::to_string).collect(),
+                Some(other) => {
+                    return Err(argument_error(format!(
+                        "argument 2 of `exec` must be a list, got {}",
+                        other.describe()
+                    )));
+                }
+            };
+            unsandboxed(interp, name)?;
+            let output = std::process::Command::new(cmd)
+                .args(&argv)
+                .output()
+                .map_err(|e| io_error(cmd, e))?;
+            Ok(Some(Value::from(vec![
+                output.status.code().map_or(Value::Nil, Value::from),
+                Value::from(String::from_utf8_lossy(&output.stdout).into_owned()),
+                Value::from(String::from_utf8_lossy(&output.stderr).into_owned()),
+            ])))
+        }
+        "format" => {
+            let Some((template, values)) = args.split_first() else {
+                return Err(argument_error(
+                    "`format` takes a template string and its values".to_string(),
+                ));
+            };
+            let Value::Str(template) = template else {
+                return Err(argument_error(format!(
+                    "argument 1 of `format` must be a string, got {}",
+                    template.describe()
+                )));
+            };
+            format(template, values).map(|s| Some(Value::from(s)))
+        }
+        // Nil when the variable is unset or not valid unicode.
+        "getenv" => {
+            arity(name, args, 1)?;
+            let var = str(name, args, 0)?;
+            unsandboxed(interp, name)?;
+            Ok(Some(std::env::var(var).map_or(Value::Nil, Value::from)))
+        }
+        // The body of a 2xx response as a string; any other status is an
+        // error.
+        #[cfg(all(feature = "net", not(target_arch = "wasm32")))]
+        "httpget" => {
+            arity(name, args, 1)?;
+            let url = str(name, args, 0)?;
+            unsandboxed(interp, name)?;
+            let body = ureq::get(url)
+                .call()
+                .map_err(|e| e.to_string())
+                .and_then(|response| response.into_string().map_err(|e| e.to_string()))
+                .map_err(|e| RuntimeError::new(RuntimeErrorKind::IoError, format!("GET {}", e)))?;
+            Ok(Some(Value::from(body)))
+        }
+        // Objects become maps and null becomes nil.
+        "jsonparse" => {
+            arity(name, args, 1)?;
+            let text = str(name, args, 0)?;
+            let json: serde_json::Value = serde_json::from_str(text)
+                .map_err(|e| argument_error(format!("`jsonparse`: invalid JSON: {}", e)))?;
+            Ok(Value::from_json(&json))
+        }
+        // Sets become arrays, ranges `{start, step, len}` objects, non-finite
+        // floats and host objects null.
+        "jsonstr" => {
+            arity(name, args, 1)?;
+            Ok(Some(Value::from(args[0].to_json().to_string())))
+        }
+        // A new list in `Value::total_cmp` order; a set or range comes back
+        // as a list and an array as an array.
+        "sort" => {
+            arity(name, args, 1)?;
+            let mut items: Vec<Value> = match &args[0] {
+                Value::List(l) => l.to_vec(),
+                Value::Range(r) => {
+                    collection_size(name, r.len())?;
+                    r.iter().map(Value::Int).collect()
+                }
+                Value::IntArray(a) => {
+                    let mut a = a.to_vec();
+                    a.sort();
+                    return Ok(Some(Value::IntArray(Shared::new(a))));
+                }
+                Value::FloatArray(a) => {
+                    let mut a = a.to_vec();
+                    a.sort_by(|a, b| a.0.total_cmp(&b.0));
+                    return Ok(Some(Value::FloatArray(Shared::new(a))));
+                }
+                Value::Set(s) => s.iter().cloned().collect(),
+                other => {
+                    return Err(argument_error(format!(
+                        "`sort` needs a list, range, array or set, got {}",
+                        other.describe()
+                    )));
+                }
+            };
+            items.sort_by(Value::total_cmp);
+            Ok(Some(Value::from(items)))
+        }
+        "typeof" => {
+            arity(name, args, 1)?;
+            Ok(Some(Value::from(args[0].type_name())))
+        }
+        // Version 4, drawn from the same generator as `rand`, so `--seed`
+        // makes it reproducible too.
+        #[cfg(feature = "uuid")]
+        "uuid" => {
+            arity(name, args, 0)?;
+            let mut bytes = [0u8; 16];
+            bytes[..8].copy_from_slice(&interp.rng.next_u64().to_le_bytes());
+            bytes[8..].copy_from_slice(&interp.rng.next_u64().to_le_bytes());
+            let uuid = uuid::Builder::from_random_bytes(bytes).into_uuid();
+            Ok(Some(Value::from(uuid.to_string())))
+        }
+        // Lowercase hex digests of the string's UTF-8 bytes.
+        #[cfg(feature = "hashing")]
+        "sha256" | "md5" => {
+            use sha2::Digest;
+            arity(name, args, 1)?;
+            let text = str(name, args, 0)?;
+            let digest = match name {
+                "sha256" => sha2::Sha256::digest(text).to_vec(),
+                _ => md5::Md5::digest(text).to_vec(),
+            };
+            let hex: String = digest.iter().map(|b| format!("{:02x}", b)).collect();
+            Ok(Some(Value::from(hex)))
+        }
+        _ => Err(RuntimeError::new(
+            RuntimeErrorKind::UnknownFunction,
+            format!("unknown function `{}`", name),
+        )),
+    }
+}
+
+// `{}` takes the next value; `{:spec}` also gives
+// `[[fill]align][width][.precision]` with align `<`, `>` or `^`, as in Rust.
+// Numbers align right by default and everything else left; a width starting
+// with `0` pads numbers with zeros. Precision sets a number's decimals and
+// cuts strings short. `{{` and `}}` are literal braces.
+fn format(template: &str, values: &[Value]) -> Result<String, RuntimeError> {
+    let mut out = String::new();
+    let mut values = values.iter();
+    let mut chars = template.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '{' if chars.peek() == Some(&'{') => {
+                chars.next();
+                out.push('{');
+            }
+            '}' if chars.peek() == Some(&'}') => {
+                chars.next();
+                out.push('}');
+            }
+            '{' => {
+                let mut spec = String::new();
+                loop {
+                    match chars.next() {
+                        Some('}') => break,
+                        Some(c) => spec.push(c),
+                        None => {
+                            return Err(argument_error(
+                                "`format`: unclosed `{` in template".to_string(),
+                            ));
+                        }
+                    }
+                }
+                let spec = match spec.strip_prefix(':') {
+                    Some(spec) => spec,
+                    None if spec.is_empty() => "",
+                    None => {
+                        return Err(argument_error(format!(
+                            "`format`: expected `{{}}` or `{{:spec}}`, found `{{{}}}`",
+                            spec
+                        )));
+                    }
+                };
+                let Some(value) = values.next() else {
+                    return Err(argument_error(
+                        "`format`: more `{}` than values".to_string(),
+                    ));
+                };
+                format_one(&mut out, spec, value)?;
+            }
+            '}' => {
+                return Err(argument_error(
+                    "`format`: unmatched `}` in template; write `}}` for a literal one".to_string(),
+                ));
+            }
+            c => out.push(c),
+        }
+    }
+    if values.next().is_some() {
+        return Err(argument_error(
+            "`format`: more values than `{}`".to_string(),
+        ));
+    }
+    Ok(out)
+}
+
+// Far wider than any sensible column, and small enough that padding to it
+// cannot exhaust memory.
+const MAX_FORMAT_WIDTH: usize = u16::MAX as usize;
+
+fn format_one(out: &mut String, spec: &str, value: &Value) -> Result<(), RuntimeError> {
+    let bad = || argument_error(format!("`format`: invalid spec `{{:{}}}`", spec));
+    let chars: Vec<char> = spec.chars().collect();
+    let is_align = |c: &char| matches!(c, '<' | '>' | '^');
+    let numeric = value.is_number();
+    let (fill, align, rest) = match chars.as_slice() {
+        [fill, align, ..] if is_align(align) => (*fill, Some(*align), &chars[2..]),
+        [align, ..] if is_align(align) => (' ', Some(*align), &chars[1..]),
+        ['0', ..] if numeric => ('0', Some('>'), &chars[1..]),
+        _ => (' ', None, &chars[..]),
+    };
+    let rest: String = rest.iter().collect();
+    let (width, precision) = match rest.split_once('.') {
+        Some((width, precision)) => (width, Some(precision.parse::<usize>().map_err(|_| bad())?)),
+        None => (rest.as_str(), None),
+    };
+    let width = match width {
+        "" => 0,
+        width => width.parse::<usize>().map_err(|_| bad())?,
+    };
+    if width.max(precision.unwrap_or(0)) > MAX_FORMAT_WIDTH {
+        return Err(argument_error(format!(
+            "`format`: width and precision must be at most {}, got `{{:{}}}`",
+            MAX_FORMAT_WIDTH, spec
+        )));
+    }
+    let text = match (value, precision) {
+        (Value::Str(s), Some(p)) => s.chars().take(p).collect(),
+        // Digit for digit, where going through an f64 would round past 2^53.
+        (Value::Int(i), Some(p)) => {
+            let mut text = i.to_string();
+            if p > 0 {
+                text.push('.');
+                text.extend(std::iter::repeat_n('0', p));
+            }
+            text
+        }
+        (Value::Float(f), Some(p)) => format!("{:.*}", p, f.0),
+        #[cfg(feature = "decimal")]
+        (Value::Decimal(d), Some(p)) => format!("{:.*}", p, d),
+        (_, Some(_)) => return Err(bad()),
+        (other, None) => other.to_string(),
+    };
+    let pad = width.saturating_sub(text.chars().count());
+    let (left, right) = match align.unwrap_or(if numeric { '>' } else { '<' }) {
+        '<' => (0, pad),
+        '>' => (pad, 0),
+        _ => (pad / 2, pad - pad / 2),
+    };
+    // Zeros go after the sign: `-0042`, not `00-42`.
+    let text = match text.strip_prefix('-') {
+        Some(digits) if numeric && fill == '0' && left > 0 => {
+            out.push('-');
+            digits
+        }
+        _ => &text,
+    };
+    out.extend(std::iter::repeat_n(fill, left));
+    out.push_str(text);
+    out.extend(std::iter::repeat_n(fill, right));
+    Ok(())
+}
+
+// RFC 4180: fields may be quoted, and inside quotes `""` is a quote and line
+// breaks are data. `None` for an unclosed quote.
+fn csv_parse(text: &str) -> Option<Vec<Vec<String>>> {
+    let mut rows = Vec::new();
+    let mut row = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                chars.next();
+                field.push('"');
+            }
+            '"' if quoted => quoted = false,
+            '"' if field.is_empty() => quoted = true,
+            ',' if !quoted => row.push(std::mem::take(&mut field)),
+            '\r' if !quoted && chars.peek() == Some(&'\n') => {}
+            '\n' if !quoted => {
+                row.push(std::mem::take(&mut field));
+                rows.push(std::mem::take(&mut row));
+            }
+            c => field.push(c),
+        }
+    }
+    if quoted {
+        return None;
+    }
+    // The last line may lack its line break.
+    if !field.is_empty() || !row.is_empty() {
+        row.push(field);
+        rows.push(row);
+    }
+    Some(rows)
+}
+
+fn csv_row(out: &mut String, fields: impl Iterator<Item = Value>) {
+    for (i, field) in fields.enumerate() {
+        if i > 0 {
+            out.push(',');
+        }
+        let text = field.to_string();
+        if text.contains([',', '"', '\n', '\r']) {
+            out.push('"');
+            out.push_str(&text.replace('"', "\"\""));
+            out.push('"');
+        } else {
+            out.push_str(&text);
+        }
+    }
+    out.push('\n');
+}
+
+const BASE64: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+fn base64_encode(bytes: &[u8]) -> String {
+    let mut out = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let n = chunk
+            .iter()
+            .enumerate()
+            .fold(0u32, |n, (i, &b)| n | (b as u32) << (16 - 8 * i));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(BASE64[(n >> (18 - 6 * i) & 63) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+// Padding is optional; anything else outside the alphabet is an error.
+fn base64_decode(text: &str) -> Option<Vec<u8>> {
+    let text = text.trim_end_matches('=');
+    // A lone leftover character cannot encode a byte.
+    if text.len() % 4 == 1 {
+        return None;
+    }
+    let mut out = Vec::with_capacity(text.len() * 3 / 4);
+    let (mut n, mut bits) = (0u32, 0);
+    for c in text.bytes() {
+        let digit = BASE64.iter().position(|&d| d == c)? as u32;
+        n = n << 6 | digit;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            out.push((n >> bits) as u8);
+        }
+    }
+    Some(out)
+}
+
+// Building a list or array of more elements than this would exhaust memory
+// long before it finished, so it is refused up front.
+const MAX_COLLECTION: u64 = 1 << 28;
+
+fn collection_size(name: &str, len: u64) -> Result<(), RuntimeError> {
+    if len > MAX_COLLECTION {
+        return Err(argument_error(format!(
+            "`{}` would build {} elements, more than the maximum of {}",
+            name, len, MAX_COLLECTION
+        )));
+    }
+    Ok(())
+}
+
+fn element_int(name: &str, value: &Value) -> Result<i64, RuntimeError> {
+    match value {
+        Value::Int(i) => Ok(*i),
+        other => Err(argument_error(format!(
+            "`{}` works on integers, got {}",
+            name,
+            other.describe()
+        ))),
+    }
+}
+
+fn element_float(name: &str, value: &Value) -> Result<f64, RuntimeError> {
+    match value {
+        Value::Int(i) => Ok(*i as f64),
+        Value::Float(f) => Ok(f.0),
+        other => Err(argument_error(format!(
+            "`{}` works on numbers, got {}",
+            name,
+            other.describe()
+        ))),
+    }
+}
+
+// An exact integer result as the interpreter's overflow policy has it.
+fn integer(interp: &Interpreter, name: &str, exact: i128) -> Result<Option<Value>, RuntimeError> {
+    match fit(interp.overflow, exact) {
+        Some(n) => Ok(Some(Value::Int(n))),
+        None if interp.overflow == Overflow::Float => Ok(Some(Value::from(exact as f64))),
+        None => Err(overflow_error(interp, name)),
+    }
+}
+
+// `None` when `exact` does not fit and the policy does not make it fit.
+fn fit(policy: Overflow, exact: i128) -> Option<i64> {
+    match (i64::try_from(exact), policy) {
+        (Ok(n), _) => Some(n),
+        (Err(_), Overflow::Wrap) => Some(exact as i64),
+        (Err(_), Overflow::Saturate) => Some(if exact < 0 { i64::MIN } else { i64::MAX }),
+        (Err(_), Overflow::Error | Overflow::Float) => None,
+    }
+}
+
+fn overflow_error(interp: &Interpreter, name: &str) -> RuntimeError {
+    RuntimeError::new(
+        RuntimeErrorKind::Overflow,
+        format!(
+            "`{}` overflowed the range of an integer (the overflow policy is `{}`)",
+            name,
+            interp.overflow.name()
+        ),
+    )
+}
+
+fn unsandboxed(interp: &Interpreter, name: &str) -> Result<(), RuntimeError> {
+    if interp.sandbox {
+        return Err(RuntimeError::new(
+            RuntimeErrorKind::Sandboxed,
+            format!("`{}` is not allowed in sandbox mode", name),
+        ));
+    }
+    Ok(())
+}
+
+fn io_error(path: &str, e: std::io::Error) -> RuntimeError {
+    RuntimeError::new(RuntimeErrorKind::IoError, format!("{}: {}", path, e))
+}
+
+fn argument_error(message: String) -> RuntimeError {
+    RuntimeError::new(RuntimeErrorKind::ArgumentError, message)
+}
+
+fn arity(name: &str, args: &[Value], expected: usize) -> Result<(), RuntimeError> {
+    if args.len() == expected {
+        return Ok(());
+    }
+    Err(argument_error(format!(
+        "`{}` takes {} argument{}, got {}",
+        name,
+        expected,
+        if expected == 1 { "" } else { "s" },
+        args.len()
+    )))
+}
+
+fn int(name: &str, args: &[Value], i: usize) -> Result<i64, RuntimeError> {
+    match &args[i] {
+        Value::Int(n) => Ok(*n),
+        other => Err(argument_error(format!(
+            "argument {} of `{}` must be an integer, got {}",
+            i + 1,
+            name,
+            other.describe()
+        ))),
+    }
+}
+
+#[cfg(feature = "decimal")]
+fn decimal_operand(value: &Value) -> Option<rust_decimal::Decimal> {
+    match value {
+        Value::Decimal(d) => Some(*d),
+        Value::Int(i) => Some(rust_decimal::Decimal::from(*i)),
+        _ => None,
+    }
+}
+
+#[cfg(feature = "decimal")]
+fn decimal_arg(
+    name: &str,
+    args: &[Value],
+    i: usize,
+) -> Result<rust_decimal::Decimal, RuntimeError> {
+    decimal_operand(&args[i]).ok_or_else(|| {
+        argument_error(format!(
+            "argument {} of `{}` must be a decimal or an integer, got {}",
+            i + 1,
+            name,
+            args[i].describe()
+        ))
+    })
+}
+
+fn str<'a>(name: &str, args: &'a [Value], i: usize) -> Result<&'a str, RuntimeError> {
+    match &args[i] {
+        Value::Str(s) => Ok(s.as_str()),
+        other => Err(argument_error(format!(
+            "argument {} of `{}` must be a string, got {}",
+            i + 1,
+            name,
+            other.describe()
+        ))),
+    }
+}
+
+// SplitMix64: small, fast and reproducible from a seed. Not for secrets.
+#[derive(Debug, Clone)]
+pub(crate) struct Rng(u64);
+
+impl Rng {
+    pub(crate) fn new(seed: u64) -> Self {
+        Rng(seed)
+    }
+    // Seeded differently on every start, for runs that did not ask for one.
+    pub(crate) fn unseeded() -> Self {
+        Rng(RandomState::new().build_hasher().finish())
+    }
+    fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+    fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
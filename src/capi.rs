@@ -0,0 +1,110 @@
+// ===== C API =====
+// Strings passed in must be NUL-terminated UTF-8. Strings returned by this
+// module are owned by the caller and must be released with `sntvm_string_free`.
+use crate::{Interpreter, Value};
+use std::{
+    ffi::{CStr, CString, c_char, c_int},
+    panic::{self, AssertUnwindSafe},
+    ptr,
+};
+
+unsafe fn str_arg<'a>(s: *const c_char) -> Option<&'a str> {
+    if s.is_null() {
+        return None;
+    }
+    unsafe { CStr::from_ptr(s) }.to_str().ok()
+}
+
+fn into_c_string(s: String) -> *mut c_char {
+    CString::new(s).map_or(ptr::null_mut(), CString::into_raw)
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn sntvm_interpreter_new() -> *mut Interpreter {
+    Box::into_raw(Box::new(Interpreter::new()))
+}
+
+/// # Safety
+/// `interp` must come from `sntvm_interpreter_new` and not have been freed.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn sntvm_interpreter_free(interp: *mut Interpreter) {
+    if !interp.is_null() {
+        drop(unsafe { Box::from_raw(interp) });
+    }
+}
+
+/// Runs `src` and returns the last statement's value as JSON (`"null"` when
+/// there is none), or NULL if the script could not be evaluated.
+///
+/// # Safety
+/// `interp` must be a live interpreter and `src` a valid C string.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn sntvm_eval(interp: *mut Interpreter, src: *const c_char) -> *mut c_char {
+    let (Some(interp), Some(src)) = (unsafe { interp.as_mut() }, unsafe { str_arg(src) }) else {
+        return ptr::null_mut();
+    };
+    match panic::catch_unwind(AssertUnwindSafe(|| interp.eval_str(src))) {
+        Ok(Ok(value)) => into_c_string(
+            value
+                .map(|v| v.to_json())
+                .unwrap_or(serde_json::Value::Null)
+                .to_string(),
+        ),
+        Ok(Err(_)) | Err(_) => ptr::null_mut(),
+    }
+}
+
+/// Returns the variable as JSON, or NULL if it is undefined.
+///
+/// # Safety
+/// `interp` must be a live interpreter and `name` a valid C string.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn sntvm_get_var(
+    interp: *const Interpreter,
+    name: *const c_char,
+) -> *mut c_char {
+    let (Some(interp), Some(name)) = (unsafe { interp.as_ref() }, unsafe { str_arg(name) }) else {
+        return ptr::null_mut();
+    };
+    match interp.world().get(name) {
+        Some(value) => into_c_string(value.to_json().to_string()),
+        None => ptr::null_mut(),
+    }
+}
+
+/// Sets the variable from a JSON document. Returns 0 on success and -1 if an
+/// argument is invalid or the JSON has no `Value` equivalent.
+///
+/// # Safety
+/// `interp` must be a live interpreter; `name` and `json` valid C strings.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn sntvm_set_var(
+    interp: *mut Interpreter,
+    name: *const c_char,
+    json: *const c_char,
+) -> c_int {
+    let (Some(interp), Some(name), Some(json)) = (
+        unsafe { interp.as_mut() },
+        unsafe { str_arg(name) },
+        unsafe { str_arg(json) },
+    ) else {
+        return -1;
+    };
+    let Some(value) = serde_json::from_str(json)
+        .ok()
+        .and_then(|json| Value::from_json(&json))
+    else {
+        return -1;
+    };
+    interp.assign(name, value);
+    0
+}
+
+/// # Safety
+/// `s` must be NULL or a string returned by this module, freed at most once.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn sntvm_string_free(s: *mut c_char) {
+    if !s.is_null() {
+        drop(unsafe { CString::from_raw(s) });
+    }
+}
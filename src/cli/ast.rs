@@ -0,0 +1,39 @@
+use super::{Args, FAILURE, SUCCESS, USAGE, read_source, report};
+use sntvm::Program;
+
+pub fn main(args: &Args) -> i32 {
+    let path = match args.file() {
+        Ok(path) => path,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            return USAGE;
+        }
+    };
+    let Some(src) = read_source(path) else {
+        return FAILURE;
+    };
+    let program = match Program::compile(&src) {
+        Ok(program) => program,
+        Err(errors) => {
+            for e in &errors {
+                report(path, &src, e);
+            }
+            return FAILURE;
+        }
+    };
+    let json = if args.flag("compact") {
+        serde_json::to_string(program.ast())
+    } else {
+        serde_json::to_string_pretty(program.ast())
+    };
+    match json {
+        Ok(json) => {
+            println!("{}", json);
+            SUCCESS
+        }
+        Err(e) => {
+            eprintln!("error: {}", e);
+            FAILURE
+        }
+    }
+}
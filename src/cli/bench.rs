@@ -0,0 +1,101 @@
+use super::{Args, FAILURE, SUCCESS, USAGE, read_source, report};
+use sntvm::{Interpreter, MemoryIo, Program};
+use std::time::{Duration, Instant};
+
+fn count(args: &Args, flag: &str, default: usize) -> Result<usize, String> {
+    match args.value(flag) {
+        None => Ok(default),
+        Some(v) => v
+            .parse()
+            .map_err(|_| format!("`--{}` expects a number, got `{}`", flag, v)),
+    }
+}
+
+// Output goes to memory so the terminal doesn't skew the timings.
+fn run_once(program: &Program) -> Result<(Duration, u64), sntvm::RuntimeError> {
+    let mut interpreter = Interpreter::builder().io(MemoryIo::new()).build();
+    let start = Instant::now();
+    interpreter.run(program)?;
+    Ok((start.elapsed(), interpreter.steps()))
+}
+
+pub fn main(args: &Args) -> i32 {
+    let path = match args.file() {
+        Ok(path) => path,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            return USAGE;
+        }
+    };
+    let (runs, warmup) = match (count(args, "runs", 10), count(args, "warmup", 2)) {
+        (Ok(runs), Ok(warmup)) if runs > 0 => (runs, warmup),
+        (Ok(_), Ok(_)) => {
+            eprintln!("error: `--runs` must be at least 1");
+            return USAGE;
+        }
+        (Err(e), _) | (_, Err(e)) => {
+            eprintln!("error: {}", e);
+            return USAGE;
+        }
+    };
+    let Some(src) = read_source(path) else {
+        return FAILURE;
+    };
+    let compile_start = Instant::now();
+    let program = match Program::compile(&src) {
+        Ok(program) => program,
+        Err(errors) => {
+            for e in &errors {
+                report(path, &src, e);
+            }
+            return FAILURE;
+        }
+    };
+    let compile_time = compile_start.elapsed();
+
+    let mut times = Vec::with_capacity(runs);
+    let mut steps = 0;
+    for i in 0..warmup + runs {
+        match run_once(&program) {
+            Ok((time, n)) if i >= warmup => {
+                times.push(time.as_secs_f64());
+                steps = n;
+            }
+            Ok(_) => {}
+            Err(e) => {
+                report(path, &src, &e);
+                return FAILURE;
+            }
+        }
+    }
+
+    times.sort_by(f64::total_cmp);
+    let n = times.len() as f64;
+    let mean = times.iter().sum::<f64>() / n;
+    let median = if times.len() % 2 == 0 {
+        (times[times.len() / 2 - 1] + times[times.len() / 2]) / 2.0
+    } else {
+        times[times.len() / 2]
+    };
+    let stddev = (times.iter().map(|t| (t - mean).powi(2)).sum::<f64>() / n).sqrt();
+    let fmt = |secs: f64| format!("{:?}", Duration::from_secs_f64(secs));
+
+    println!("{}: {} runs after {} warmup", path, runs, warmup);
+    println!("  compile     {:?}", compile_time);
+    println!("  mean        {}", fmt(mean));
+    println!("  median      {}", fmt(median));
+    println!("  stddev      {}", fmt(stddev));
+    println!(
+        "  min / max   {} / {}",
+        fmt(times[0]),
+        fmt(times[times.len() - 1])
+    );
+    if mean > 0.0 {
+        println!(
+            "  throughput  {:.0} statements/s ({} per run)",
+            steps as f64 / mean,
+            steps
+        );
+    }
+    SUCCESS
+}
@@ -0,0 +1,49 @@
+use super::{Args, FAILURE, SUCCESS, USAGE, read_source, report};
+use sntvm::{Program, analysis};
+
+// Prints every problem found in `path` and returns how many there were.
+fn check_file(path: &str, undefined: bool) -> usize {
+    let Some(src) = read_source(path) else {
+        return 1;
+    };
+    let mut problems = 0;
+    match Program::compile(&src) {
+        Ok(program) if undefined => {
+            for d in analysis::undefined_variables(program.ast(), &["argc", "args"]) {
+                report(path, &src, &d);
+                problems += 1;
+            }
+        }
+        Ok(_) => {}
+        Err(errors) => {
+            for e in &errors {
+                report(path, &src, e);
+            }
+            problems += errors.len();
+        }
+    }
+    problems
+}
+
+pub fn main(args: &Args) -> i32 {
+    if args.positional.is_empty() {
+        eprintln!("error: missing <file> argument");
+        return USAGE;
+    }
+    // Strict by default, like `test`.
+    let undefined = !args.flag("lenient");
+    let problems: usize = args
+        .positional
+        .iter()
+        .map(|path| check_file(path, undefined))
+        .sum();
+    if problems > 0 {
+        eprintln!(
+            "{} problem{} found",
+            problems,
+            if problems == 1 { "" } else { "s" }
+        );
+        return FAILURE;
+    }
+    SUCCESS
+}
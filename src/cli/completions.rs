@@ -0,0 +1,195 @@
+use super::{Args, COMMANDS, Command, SUCCESS, USAGE};
+use std::fmt::Write;
+
+const SHELLS: &[&str] = &["bash", "zsh", "fish"];
+
+// Everything below is generated from `COMMANDS`, so new commands and flags
+// complete without touching this file. `help` and `completions` also
+// complete their single argument.
+fn names() -> Vec<&'static str> {
+    COMMANDS.iter().map(|c| c.name).chain(["help"]).collect()
+}
+
+fn flags(command: &Command) -> Vec<String> {
+    let mut flags: Vec<String> = command
+        .flags
+        .iter()
+        .map(|f| format!("--{}", f.long))
+        .collect();
+    flags.push("--help".to_string());
+    flags
+}
+
+fn bash() -> String {
+    let mut out = String::new();
+    let _ = writeln!(out, "_sntvm() {{");
+    let _ = writeln!(out, "    local cur=\"${{COMP_WORDS[COMP_CWORD]}}\"");
+    let _ = writeln!(out, "    if [ \"$COMP_CWORD\" -eq 1 ]; then");
+    let _ = writeln!(
+        out,
+        "        COMPREPLY=($(compgen -W \"{} -h --help -V --version\" -- \"$cur\"))",
+        names().join(" ")
+    );
+    let _ = writeln!(out, "        return");
+    let _ = writeln!(out, "    fi");
+    let _ = writeln!(out, "    local words");
+    let _ = writeln!(out, "    case \"${{COMP_WORDS[1]}}\" in");
+    let _ = writeln!(out, "        help) words=\"{}\" ;;", names().join(" "));
+    let _ = writeln!(
+        out,
+        "        completions) words=\"{}\" ;;",
+        SHELLS.join(" ")
+    );
+    for command in COMMANDS {
+        let _ = writeln!(
+            out,
+            "        {}) [[ \"$cur\" == -* ]] && words=\"{}\" ;;",
+            command.name,
+            flags(command).join(" ")
+        );
+    }
+    let _ = writeln!(out, "    esac");
+    let _ = writeln!(out, "    if [ -n \"$words\" ]; then");
+    let _ = writeln!(
+        out,
+        "        COMPREPLY=($(compgen -W \"$words\" -- \"$cur\"))"
+    );
+    let _ = writeln!(out, "    fi");
+    let _ = writeln!(out, "}}");
+    let _ = writeln!(out, "complete -o default -F _sntvm sntvm");
+    out
+}
+
+// Inside zsh single quotes, where `:` and `[]` also mean something to
+// `_describe` and `_arguments`.
+fn zsh_quote(text: &str) -> String {
+    text.replace('\'', "'\\''")
+        .replace(':', "\\:")
+        .replace('[', "\\[")
+        .replace(']', "\\]")
+}
+
+fn zsh() -> String {
+    let mut out = String::new();
+    let _ = writeln!(out, "#compdef sntvm");
+    let _ = writeln!(out);
+    let _ = writeln!(out, "_sntvm() {{");
+    let _ = writeln!(out, "    local -a commands");
+    let _ = writeln!(out, "    commands=(");
+    for command in COMMANDS {
+        let _ = writeln!(
+            out,
+            "        '{}:{}'",
+            command.name,
+            zsh_quote(command.about)
+        );
+    }
+    let _ = writeln!(out, "        'help:Show help for a command'");
+    let _ = writeln!(out, "    )");
+    let _ = writeln!(out, "    if (( CURRENT == 2 )); then");
+    let _ = writeln!(out, "        _describe 'command' commands");
+    let _ = writeln!(out, "        _files");
+    let _ = writeln!(out, "        return");
+    let _ = writeln!(out, "    fi");
+    let _ = writeln!(out, "    case $words[2] in");
+    let _ = writeln!(out, "        help) _describe 'command' commands ;;");
+    let _ = writeln!(
+        out,
+        "        completions) _values 'shell' {} ;;",
+        SHELLS.join(" ")
+    );
+    for command in COMMANDS {
+        let mut specs: Vec<String> = command
+            .flags
+            .iter()
+            .map(|f| {
+                let value = match f.value {
+                    Some(meta) => format!(":{}:", zsh_quote(meta)),
+                    None => String::new(),
+                };
+                format!("'--{}[{}]{}'", f.long, zsh_quote(f.about), value)
+            })
+            .collect();
+        specs.push("'--help[Show this message]'".to_string());
+        specs.push("'*:file:_files'".to_string());
+        let _ = writeln!(
+            out,
+            "        {}) _arguments {} ;;",
+            command.name,
+            specs.join(" ")
+        );
+    }
+    let _ = writeln!(out, "    esac");
+    let _ = writeln!(out, "}}");
+    let _ = writeln!(out);
+    let _ = writeln!(out, "_sntvm \"$@\"");
+    out
+}
+
+fn fish_quote(text: &str) -> String {
+    format!("'{}'", text.replace('\\', "\\\\").replace('\'', "\\'"))
+}
+
+fn fish() -> String {
+    let mut out = String::new();
+    let all = names().join(" ");
+    let _ = writeln!(
+        out,
+        "complete -c sntvm -n __fish_use_subcommand -a help -d 'Show help for a command'"
+    );
+    for command in COMMANDS {
+        let _ = writeln!(
+            out,
+            "complete -c sntvm -n __fish_use_subcommand -a {} -d {}",
+            command.name,
+            fish_quote(command.about)
+        );
+    }
+    let _ = writeln!(
+        out,
+        "complete -c sntvm -n '__fish_seen_subcommand_from help' -f -a '{}'",
+        all
+    );
+    let _ = writeln!(
+        out,
+        "complete -c sntvm -n '__fish_seen_subcommand_from completions' -f -a '{}'",
+        SHELLS.join(" ")
+    );
+    for command in COMMANDS {
+        for flag in command.flags {
+            let _ = writeln!(
+                out,
+                "complete -c sntvm -n '__fish_seen_subcommand_from {}' -l {}{} -d {}",
+                command.name,
+                flag.long,
+                if flag.value.is_some() { " -r" } else { "" },
+                fish_quote(flag.about)
+            );
+        }
+    }
+    out
+}
+
+pub fn main(args: &Args) -> i32 {
+    let shell = match args.file() {
+        Ok(shell) => shell,
+        Err(e) => {
+            eprintln!("error: {}", e.replace("<file>", "<shell>"));
+            return USAGE;
+        }
+    };
+    match shell {
+        "bash" => print!("{}", bash()),
+        "zsh" => print!("{}", zsh()),
+        "fish" => print!("{}", fish()),
+        other => {
+            eprintln!(
+                "error: unsupported shell `{}` (expected {})",
+                other,
+                SHELLS.join(", ")
+            );
+            return USAGE;
+        }
+    }
+    SUCCESS
+}
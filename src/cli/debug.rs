@@ -0,0 +1,199 @@
+use super::{Args, FAILURE, SUCCESS, USAGE, line_of, read_source, report};
+use sntvm::{Interpreter, Pause, Program, Resume, RuntimeErrorKind};
+use std::{
+    collections::BTreeSet,
+    io::{self, BufRead, Write},
+};
+
+const DEBUG_HELP: &str = "\
+break <line>   stop when execution reaches a line (no line: list breakpoints)
+step           run one statement, entering branch bodies
+next           run one statement, stepping over branch bodies
+continue       run until the next breakpoint
+print <var>    show a variable, its generation and any pending branch
+branches       show branches waiting to be merged
+backtrace      show the branches the current statement is inside
+quit           abandon the run (or Ctrl-D)";
+
+// When the stepper hands control back to the user.
+enum Mode {
+    Step,
+    // Stop at the next statement nested at most this deep.
+    Next(usize),
+    Continue,
+}
+
+struct Debugger {
+    src: String,
+    breakpoints: BTreeSet<usize>,
+    mode: Mode,
+    // Line of the previous statement, so a breakpoint fires once per line.
+    last_line: usize,
+}
+
+impl Debugger {
+    fn should_pause(&self, pause: &Pause, line: usize) -> bool {
+        match self.mode {
+            Mode::Step => true,
+            Mode::Next(depth) if pause.stack.len() <= depth => true,
+            _ => line != self.last_line && self.breakpoints.contains(&line),
+        }
+    }
+
+    fn on_pause(&mut self, pause: &Pause) -> Resume {
+        let line = line_of(&self.src, pause.node.span().start);
+        let paused = self.should_pause(pause, line);
+        self.last_line = line;
+        if !paused {
+            return Resume::Continue;
+        }
+        self.show_position(pause, line);
+        let stdin = io::stdin();
+        loop {
+            print!("(debug) ");
+            io::stdout().flush().ok();
+            let mut input = String::new();
+            if !matches!(stdin.lock().read_line(&mut input), Ok(n) if n > 0) {
+                println!();
+                return Resume::Stop;
+            }
+            let mut words = input.split_whitespace();
+            match (words.next(), words.next()) {
+                (None, _) => {}
+                (Some("step" | "s"), _) => {
+                    self.mode = Mode::Step;
+                    return Resume::Continue;
+                }
+                (Some("next" | "n"), _) => {
+                    self.mode = Mode::Next(pause.stack.len());
+                    return Resume::Continue;
+                }
+                (Some("continue" | "c"), _) => {
+                    self.mode = Mode::Continue;
+                    return Resume::Continue;
+                }
+                (Some("quit" | "q"), _) => return Resume::Stop,
+                (Some("break" | "b"), None) => {
+                    for line in &self.breakpoints {
+                        println!("breakpoint at line {}", line);
+                    }
+                }
+                (Some("break" | "b"), Some(arg)) => match arg.parse::<usize>() {
+                    Ok(line) if line > 0 => {
+                        self.breakpoints.insert(line);
+                        println!("breakpoint at line {}", line);
+                    }
+                    _ => println!("expected a line number, got `{}`", arg),
+                },
+                (Some("print" | "p"), Some(name)) => show_var(pause, name),
+                (Some("print" | "p"), None) => println!("usage: print <var>"),
+                (Some("branches"), _) => show_branches(pause),
+                (Some("backtrace" | "bt"), _) => self.show_backtrace(pause, line),
+                (Some("help" | "h"), _) => println!("{}", DEBUG_HELP),
+                (Some(cmd), _) => println!("unknown command `{}` (try help)", cmd),
+            }
+        }
+    }
+
+    fn excerpt(&self, start: usize, end: usize) -> &str {
+        self.src[start..end].lines().next().unwrap_or("").trim()
+    }
+
+    fn show_position(&self, pause: &Pause, line: usize) {
+        let span = pause.node.span();
+        println!("{:>4} | {}", line, self.excerpt(span.start, span.end));
+    }
+
+    fn show_backtrace(&self, pause: &Pause, line: usize) {
+        let span = pause.node.span();
+        println!("#0 line {}: {}", line, self.excerpt(span.start, span.end));
+        for (i, outer) in pause.stack.iter().rev().enumerate() {
+            println!(
+                "#{} line {}: {}",
+                i + 1,
+                line_of(&self.src, outer.start),
+                self.excerpt(outer.start, outer.end)
+            );
+        }
+    }
+}
+
+fn show_var(pause: &Pause, name: &str) {
+    match pause.world.get(name) {
+        Some(value) => println!(
+            "{} = {:?} (generation {})",
+            name,
+            value,
+            pause.world.get_gen(name)
+        ),
+        None => println!("{} is undefined", name),
+    }
+    if let Some(branch) = pause.branch(name) {
+        println!(
+            "branch on {} waiting to merge (opened at generation {})",
+            name,
+            branch.generation()
+        );
+    }
+}
+
+fn show_branches(pause: &Pause) {
+    let mut branches: Vec<_> = pause.branches().collect();
+    if branches.is_empty() {
+        println!("no branches waiting to be merged");
+    }
+    branches.sort_by_key(|b| b.variable());
+    for b in branches {
+        println!(
+            "{} (generation {}, {} nested)",
+            b.variable(),
+            b.generation(),
+            b.nested().len()
+        );
+    }
+}
+
+pub fn main(args: &Args) -> i32 {
+    let path = match args.file() {
+        Ok(path) => path,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            return USAGE;
+        }
+    };
+    let Some(code) = read_source(path) else {
+        return FAILURE;
+    };
+    let program = match Program::compile(&code) {
+        Ok(program) => program,
+        Err(errors) => {
+            for e in &errors {
+                report(path, &code, e);
+            }
+            return FAILURE;
+        }
+    };
+
+    println!("debugging {} (help for commands)", path);
+    let mut debugger = Debugger {
+        src: code.clone(),
+        breakpoints: BTreeSet::new(),
+        mode: Mode::Step,
+        last_line: 0,
+    };
+    let mut interpreter = Interpreter::builder()
+        .stepper(move |pause| debugger.on_pause(pause))
+        .build();
+    match interpreter.run(&program) {
+        Ok(()) => {
+            let status = interpreter.exit_code().unwrap_or(SUCCESS);
+            println!("program finished with exit code {}", status);
+            status
+        }
+        Err(e) if e.kind == RuntimeErrorKind::Stopped => FAILURE,
+        Err(e) => {
+            report(path, &code, &e);
+            FAILURE
+        }
+    }
+}
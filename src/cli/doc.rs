@@ -0,0 +1,73 @@
+use super::{Args, FAILURE, SUCCESS, USAGE, read_source, report};
+use sntvm::doc::{self, DocItem};
+use std::path::Path;
+
+fn markdown(title: &str, items: &[DocItem]) -> String {
+    let mut out = format!("# {}\n", title);
+    for item in items {
+        out.push_str(&format!(
+            "\n## `{}`\n\n```\n{}\n```\n\n{}\n",
+            item.name, item.declaration, item.docs
+        ));
+    }
+    out
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+// Doc text is shown as written; blank lines separate paragraphs.
+fn html(title: &str, items: &[DocItem]) -> String {
+    let title = escape(title);
+    let mut out = format!(
+        "<!DOCTYPE html>\n<html>\n<head><meta charset=\"utf-8\"><title>{0}</title></head>\n<body>\n<h1>{0}</h1>\n",
+        title
+    );
+    for item in items {
+        out.push_str(&format!(
+            "<h2 id=\"{0}\"><code>{0}</code></h2>\n<pre><code>{1}</code></pre>\n",
+            escape(&item.name),
+            escape(&item.declaration)
+        ));
+        for paragraph in item.docs.split("\n\n").filter(|p| !p.trim().is_empty()) {
+            out.push_str(&format!("<p>{}</p>\n", escape(paragraph.trim())));
+        }
+    }
+    out.push_str("</body>\n</html>\n");
+    out
+}
+
+pub fn main(args: &Args) -> i32 {
+    let path = match args.file() {
+        Ok(path) => path,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            return USAGE;
+        }
+    };
+    let Some(src) = read_source(path) else {
+        return FAILURE;
+    };
+    let items = match doc::extract(&src) {
+        Ok(items) => items,
+        Err(errors) => {
+            for e in &errors {
+                report(path, &src, e);
+            }
+            return FAILURE;
+        }
+    };
+    let title = Path::new(path)
+        .file_name()
+        .map_or(path.into(), |name| name.to_string_lossy());
+    if args.flag("html") {
+        print!("{}", html(&title, &items));
+    } else {
+        print!("{}", markdown(&title, &items));
+    }
+    SUCCESS
+}
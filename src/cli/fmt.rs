@@ -0,0 +1,43 @@
+use super::{Args, FAILURE, SUCCESS, USAGE, read_source, report};
+use sntvm::formatter;
+use std::fs;
+
+// Formats one file; `Ok(true)` if it was already formatted.
+fn format_file(path: &str, args: &Args) -> Result<bool, ()> {
+    let src = read_source(path).ok_or(())?;
+    let formatted = formatter::format_source(&src).map_err(|errors| {
+        for e in &errors {
+            report(path, &src, e);
+        }
+    })?;
+    let unchanged = formatted == src;
+    if args.flag("check") {
+        if !unchanged {
+            println!("{}", path);
+        }
+    } else if args.flag("write") {
+        if !unchanged && let Err(e) = fs::write(path, &formatted) {
+            eprintln!("error: cannot write {}: {}", path, e);
+            return Err(());
+        }
+    } else {
+        print!("{}", formatted);
+    }
+    Ok(unchanged)
+}
+
+pub fn main(args: &Args) -> i32 {
+    if args.positional.is_empty() {
+        eprintln!("error: missing <file> argument");
+        return USAGE;
+    }
+    let mut status = SUCCESS;
+    for path in &args.positional {
+        match format_file(path, args) {
+            Ok(false) if args.flag("check") => status = FAILURE,
+            Ok(_) => {}
+            Err(()) => status = FAILURE,
+        }
+    }
+    status
+}
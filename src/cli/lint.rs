@@ -0,0 +1,93 @@
+use super::{Args, FAILURE, SUCCESS, USAGE, read_source, report};
+use sntvm::{
+    Program, Report,
+    analysis::{self, Rule},
+};
+use std::collections::HashMap;
+
+#[derive(Clone, Copy, PartialEq)]
+enum Level {
+    Allow,
+    Warn,
+    Deny,
+}
+
+// Every rule warns unless `--allow`/`--warn`/`--deny` say otherwise. Each flag
+// takes a comma-separated list of rule names, or `all`.
+fn levels(args: &Args) -> Result<HashMap<Rule, Level>, String> {
+    let mut levels: HashMap<Rule, Level> = Rule::ALL.iter().map(|&r| (r, Level::Warn)).collect();
+    for (flag, level) in [
+        ("allow", Level::Allow),
+        ("warn", Level::Warn),
+        ("deny", Level::Deny),
+    ] {
+        for name in args.value(flag).into_iter().flat_map(|v| v.split(',')) {
+            let rules = match name.trim() {
+                "all" => Rule::ALL.to_vec(),
+                name => vec![Rule::from_name(name).ok_or_else(|| {
+                    let known: Vec<_> = Rule::ALL.iter().map(|r| r.name()).collect();
+                    format!("unknown lint `{}` (known: {})", name, known.join(", "))
+                })?],
+            };
+            for rule in rules {
+                levels.insert(rule, level);
+            }
+        }
+    }
+    Ok(levels)
+}
+
+pub fn main(args: &Args) -> i32 {
+    if args.positional.is_empty() {
+        eprintln!("error: missing <file> argument");
+        return USAGE;
+    }
+    let levels = match levels(args) {
+        Ok(levels) => levels,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            return USAGE;
+        }
+    };
+    let (mut warnings, mut errors) = (0, 0);
+    for path in &args.positional {
+        let Some(src) = read_source(path) else {
+            errors += 1;
+            continue;
+        };
+        let program = match Program::compile(&src) {
+            Ok(program) => program,
+            Err(parse_errors) => {
+                for e in &parse_errors {
+                    report(path, &src, e);
+                }
+                errors += parse_errors.len();
+                continue;
+            }
+        };
+        for lint in analysis::lint(program.ast()) {
+            let severity = match levels[&lint.rule] {
+                Level::Allow => continue,
+                Level::Warn => {
+                    warnings += 1;
+                    "warning"
+                }
+                Level::Deny => {
+                    errors += 1;
+                    "error"
+                }
+            };
+            let name = lint.rule.name();
+            let diagnostic = Report {
+                severity,
+                ..Report::from(&lint.diagnostic)
+            }
+            .with_hint(format!("`{}` (`--allow {}` to silence)", name, name));
+            report(path, &src, diagnostic);
+        }
+    }
+    if warnings + errors > 0 {
+        eprintln!("{} warning(s), {} error(s)", warnings, errors);
+    }
+    if errors > 0 { FAILURE } else { SUCCESS }
+}
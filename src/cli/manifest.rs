@@ -0,0 +1,107 @@
+// ===== snt.toml =====
+// A project is a directory holding `snt.toml`:
+//
+//     [package]
+//     entry = "src/main.snt"
+//     include = ["lib"]
+//
+//     [run]
+//     fuel = 100000
+//     timeout-ms = 500
+//     dump-world = "diff"
+//     seed = 42
+//     sandbox = true
+//     strict = true
+//     overflow = "wrap"
+//
+// Paths are relative to the manifest. `include` lists where `import` will
+// look for other files; until it exists the directories are only checked.
+// `sntvm test` with no paths runs the scripts under the project's `tests`.
+use serde::Deserialize;
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+};
+
+pub const FILE_NAME: &str = "snt.toml";
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Manifest {
+    pub package: Package,
+    #[serde(default)]
+    pub run: RunOptions,
+    // Directory the manifest was read from.
+    #[serde(skip)]
+    pub root: PathBuf,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Package {
+    #[serde(default = "default_entry")]
+    pub entry: PathBuf,
+    #[serde(default)]
+    pub include: Vec<PathBuf>,
+}
+
+fn default_entry() -> PathBuf {
+    PathBuf::from("main.snt")
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+pub struct RunOptions {
+    pub fuel: Option<u64>,
+    pub timeout_ms: Option<u64>,
+    pub dump_world: Option<String>,
+    pub seed: Option<u64>,
+    #[serde(default)]
+    pub sandbox: bool,
+    #[serde(default)]
+    pub strict: bool,
+    pub overflow: Option<String>,
+}
+
+impl Manifest {
+    // Looks for `snt.toml` in the current directory and then its parents,
+    // the way cargo finds `Cargo.toml`. `Ok(None)` when there is none.
+    pub fn discover() -> Result<Option<Manifest>, String> {
+        let cwd =
+            env::current_dir().map_err(|e| format!("cannot read current directory: {}", e))?;
+        match cwd
+            .ancestors()
+            .map(|dir| dir.join(FILE_NAME))
+            .find(|p| p.is_file())
+        {
+            Some(path) => Manifest::load(&path).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    pub fn load(path: &Path) -> Result<Manifest, String> {
+        let text = fs::read_to_string(path)
+            .map_err(|e| format!("cannot read {}: {}", path.display(), e))?;
+        let mut manifest: Manifest =
+            toml::from_str(&text).map_err(|e| format!("invalid {}: {}", path.display(), e))?;
+        manifest.root = path.parent().unwrap_or(Path::new(".")).to_path_buf();
+        for dir in manifest.include_paths() {
+            if !dir.is_dir() {
+                return Err(format!(
+                    "invalid {}: include path {} is not a directory",
+                    path.display(),
+                    dir.display()
+                ));
+            }
+        }
+        Ok(manifest)
+    }
+
+    pub fn entry(&self) -> PathBuf {
+        self.root.join(&self.package.entry)
+    }
+
+    pub fn include_paths(&self) -> impl Iterator<Item = PathBuf> + '_ {
+        self.package.include.iter().map(|dir| self.root.join(dir))
+    }
+}
@@ -0,0 +1,423 @@
+// ===== Command-line interface =====
+// Hand-rolled rather than clap: the surface is small, and `COMMANDS` is the
+// single definition that help output is generated from.
+mod ast;
+mod bench;
+mod check;
+mod completions;
+mod debug;
+mod doc;
+mod fmt;
+mod lint;
+mod manifest;
+mod new;
+mod profile;
+mod repl;
+mod run;
+mod test;
+mod tokens;
+
+use sntvm::Report;
+use std::{collections::HashMap, fs};
+
+pub struct Flag {
+    pub long: &'static str,
+    // Name of the flag's value in help output; `None` for boolean switches.
+    pub value: Option<&'static str>,
+    pub about: &'static str,
+}
+
+pub struct Command {
+    pub name: &'static str,
+    pub usage: &'static str,
+    pub about: &'static str,
+    pub flags: &'static [Flag],
+    pub run: fn(&Args) -> i32,
+}
+
+pub const COMMANDS: &[Command] = &[
+    Command {
+        name: "run",
+        usage: "[<file>] [--] [args]...",
+        about: "Run a script, or the snt.toml entry point; extra arguments become `args` and `argc`",
+        flags: &[
+            Flag {
+                long: "cache-dir",
+                value: Some("dir"),
+                about: "Keep compiled scripts in a directory and reuse them while the source is unchanged",
+            },
+            Flag {
+                long: "dump-world",
+                value: Some("mode"),
+                about: "Show the world: none (default), debug, json or diff",
+            },
+            Flag {
+                long: "overflow",
+                value: Some("policy"),
+                about: "Integer overflow is an error (default), or wrap, saturate or float",
+            },
+            Flag {
+                long: "profile",
+                value: Some("file"),
+                about: "Write timings of every statement to a file",
+            },
+            Flag {
+                long: "profile-format",
+                value: Some("format"),
+                about: "Profile as text (per line, the default) or chrome (trace events)",
+            },
+            Flag {
+                long: "sandbox",
+                value: None,
+                about: "Deny builtins that touch the host: `getenv`, `exec`, files and the network",
+            },
+            Flag {
+                long: "seed",
+                value: Some("n"),
+                about: "Seed `rand` and `randint` so runs are reproducible",
+            },
+            Flag {
+                long: "strict",
+                value: None,
+                about: "Make printing or updating an undefined variable an error",
+            },
+            Flag {
+                long: "stdin-from",
+                value: Some("file"),
+                about: "Answer `input` statements with the file's lines; running out is an error",
+            },
+            Flag {
+                long: "step",
+                value: None,
+                about: "Pause before each statement and show what it touches",
+            },
+            Flag {
+                long: "stream",
+                value: None,
+                about: "Run statements as they are read, without loading the whole file; `-` is stdin",
+            },
+        ],
+        run: run::main,
+    },
+    Command {
+        name: "ast",
+        usage: "<file>",
+        about: "Print the parsed syntax tree as JSON",
+        flags: &[Flag {
+            long: "compact",
+            value: None,
+            about: "Print on a single line",
+        }],
+        run: ast::main,
+    },
+    Command {
+        name: "bench",
+        usage: "<file>",
+        about: "Time repeated runs of a script",
+        flags: &[
+            Flag {
+                long: "runs",
+                value: Some("n"),
+                about: "Number of timed runs (default 10)",
+            },
+            Flag {
+                long: "warmup",
+                value: Some("n"),
+                about: "Untimed runs before measuring (default 2)",
+            },
+        ],
+        run: bench::main,
+    },
+    Command {
+        name: "check",
+        usage: "<file>...",
+        about: "Lex and parse scripts without running them",
+        flags: &[Flag {
+            long: "lenient",
+            value: None,
+            about: "Do not report variables used before they are defined",
+        }],
+        run: check::main,
+    },
+    Command {
+        name: "completions",
+        usage: "<bash|zsh|fish>",
+        about: "Print a shell completion script",
+        flags: &[],
+        run: completions::main,
+    },
+    Command {
+        name: "debug",
+        usage: "<file>",
+        about: "Run a script under an interactive debugger",
+        flags: &[],
+        run: debug::main,
+    },
+    Command {
+        name: "doc",
+        usage: "<file>",
+        about: "Print the `///` comments on a script's top-level `let`s as Markdown",
+        flags: &[Flag {
+            long: "html",
+            value: None,
+            about: "Print a standalone HTML page instead",
+        }],
+        run: doc::main,
+    },
+    Command {
+        name: "fmt",
+        usage: "<file>...",
+        about: "Print scripts in canonical layout",
+        flags: &[
+            Flag {
+                long: "check",
+                value: None,
+                about: "List files that are not formatted and exit non-zero",
+            },
+            Flag {
+                long: "write",
+                value: None,
+                about: "Rewrite the files in place",
+            },
+        ],
+        run: fmt::main,
+    },
+    Command {
+        name: "lint",
+        usage: "<file>...",
+        about: "Report likely mistakes (unused variables, unmerged branches, ...)",
+        flags: &[
+            Flag {
+                long: "allow",
+                value: Some("rules"),
+                about: "Silence these lints (comma-separated, or `all`)",
+            },
+            Flag {
+                long: "warn",
+                value: Some("rules"),
+                about: "Report these lints as warnings (the default)",
+            },
+            Flag {
+                long: "deny",
+                value: Some("rules"),
+                about: "Report these lints as errors and exit non-zero",
+            },
+        ],
+        run: lint::main,
+    },
+    Command {
+        name: "new",
+        usage: "<path>",
+        about: "Create a project with snt.toml, main.snt and a tests folder",
+        flags: &[],
+        run: new::main,
+    },
+    Command {
+        name: "repl",
+        usage: "",
+        about: "Start an interactive session (the default with no arguments)",
+        flags: &[],
+        run: repl::main,
+    },
+    Command {
+        name: "test",
+        usage: "[path]...",
+        about: "Run .snt scripts and check them against their expected output",
+        flags: &[Flag {
+            long: "lenient",
+            value: None,
+            about: "Let scripts print or update undefined variables without failing",
+        }],
+        run: test::main,
+    },
+    Command {
+        name: "tokens",
+        usage: "<file>",
+        about: "Print the token stream with source positions",
+        flags: &[],
+        run: tokens::main,
+    },
+];
+
+// Exit codes.
+pub const SUCCESS: i32 = 0;
+pub const FAILURE: i32 = 1;
+pub const USAGE: i32 = 2;
+
+#[derive(Debug, Default)]
+pub struct Args {
+    pub positional: Vec<String>,
+    flags: HashMap<&'static str, Option<String>>,
+    // Everything after a bare `--`.
+    pub rest: Vec<String>,
+}
+
+impl Args {
+    pub fn flag(&self, long: &str) -> bool {
+        self.flags.contains_key(long)
+    }
+    pub fn value(&self, long: &str) -> Option<&str> {
+        self.flags.get(long)?.as_deref()
+    }
+    // The single positional argument a command requires.
+    pub fn file(&self) -> Result<&str, String> {
+        match self.positional.as_slice() {
+            [file] => Ok(file),
+            [] => Err("missing <file> argument".to_string()),
+            [_, extra, ..] => Err(format!("unexpected argument `{}`", extra)),
+        }
+    }
+}
+
+// ===== Shared helpers =====
+// Reads a script, reporting failures the way every command does.
+pub fn read_source(path: &str) -> Option<String> {
+    match fs::read_to_string(path) {
+        Ok(src) => Some(src),
+        Err(e) => {
+            eprintln!("error: cannot read {}: {}", path, e);
+            None
+        }
+    }
+}
+
+// 1-based line number of a byte offset.
+pub fn line_of(src: &str, offset: usize) -> usize {
+    src[..offset.min(src.len())].matches('\n').count() + 1
+}
+
+// Prints a diagnostic with the offending source line underlined.
+pub fn report(path: &str, src: &str, diagnostic: impl Into<Report>) {
+    eprintln!("{}", diagnostic.into().render(path, src));
+}
+
+fn parse_args(command: &Command, raw: &[String]) -> Result<Args, String> {
+    let mut args = Args::default();
+    let mut iter = raw.iter();
+    while let Some(arg) = iter.next() {
+        if arg == "--" {
+            args.rest = iter.cloned().collect();
+            break;
+        }
+        let Some(long) = arg.strip_prefix("--") else {
+            args.positional.push(arg.clone());
+            continue;
+        };
+        let (name, inline) = match long.split_once('=') {
+            Some((name, value)) => (name, Some(value.to_string())),
+            None => (long, None),
+        };
+        let Some(flag) = command.flags.iter().find(|f| f.long == name) else {
+            return Err(format!("unknown flag `--{}` for `{}`", name, command.name));
+        };
+        let value = match (flag.value, inline) {
+            (None, None) => None,
+            (None, Some(_)) => return Err(format!("`--{}` takes no value", name)),
+            (Some(_), Some(value)) => Some(value),
+            (Some(meta), None) => match iter.next() {
+                Some(value) => Some(value.clone()),
+                None => return Err(format!("`--{}` expects a {}", name, meta)),
+            },
+        };
+        args.flags.insert(flag.long, value);
+    }
+    Ok(args)
+}
+
+fn find(name: &str) -> Option<&'static Command> {
+    COMMANDS.iter().find(|c| c.name == name)
+}
+
+fn print_help() {
+    println!("sntvm {}", env!("CARGO_PKG_VERSION"));
+    println!();
+    println!("Usage: sntvm <command> [options]");
+    println!("       sntvm <file>      same as `sntvm run <file>`");
+    println!();
+    println!("Commands:");
+    // Two spaces after the longest name.
+    let width = COMMANDS.iter().map(|c| c.name.len()).max().unwrap_or(0) + 2;
+    for c in COMMANDS {
+        println!("  {:<width$}{}", c.name, c.about);
+    }
+    println!("  {:<width$}Show help for a command", "help");
+    println!();
+    println!("Options:");
+    println!("  -h, --help     Show this message");
+    println!("  -V, --version  Show the version");
+}
+
+fn print_command_help(command: &Command) {
+    println!("{}", command.about);
+    println!();
+    println!("Usage: sntvm {} [options] {}", command.name, command.usage);
+    println!();
+    println!("Options:");
+    let names: Vec<String> = command
+        .flags
+        .iter()
+        .map(|flag| match flag.value {
+            Some(meta) => format!("--{} <{}>", flag.long, meta),
+            None => format!("--{}", flag.long),
+        })
+        .collect();
+    // Two spaces after the longest name.
+    let help = "-h, --help";
+    let width = names.iter().map(String::len).fold(help.len(), usize::max) + 2;
+    for (name, flag) in names.iter().zip(command.flags) {
+        println!("  {:<width$}{}", name, flag.about);
+    }
+    println!("  {:<width$}Show this message", help);
+}
+
+pub fn main(raw: Vec<String>) -> i32 {
+    let (command, rest) = match raw.first().map(String::as_str) {
+        None => (find("repl").unwrap(), &raw[..0]),
+        Some("-h" | "--help") => {
+            print_help();
+            return SUCCESS;
+        }
+        Some("-V" | "--version") => {
+            println!("sntvm {}", env!("CARGO_PKG_VERSION"));
+            return SUCCESS;
+        }
+        Some("help") => {
+            match raw.get(1) {
+                None => print_help(),
+                Some(name) => match find(name) {
+                    Some(command) => print_command_help(command),
+                    None => {
+                        eprintln!("error: unknown command `{}`", name);
+                        return USAGE;
+                    }
+                },
+            }
+            return SUCCESS;
+        }
+        Some(name) => match find(name) {
+            Some(command) => (command, &raw[1..]),
+            // `sntvm script.snt`
+            None if !name.starts_with('-') => (find("run").unwrap(), &raw[..]),
+            None => {
+                eprintln!("error: unknown option `{}` (see `sntvm --help`)", name);
+                return USAGE;
+            }
+        },
+    };
+    if rest
+        .iter()
+        .take_while(|a| *a != "--")
+        .any(|a| a == "-h" || a == "--help")
+    {
+        print_command_help(command);
+        return SUCCESS;
+    }
+    match parse_args(command, rest) {
+        Ok(args) => (command.run)(&args),
+        Err(e) => {
+            eprintln!("error: {}", e);
+            eprintln!("(see `sntvm help {}`)", command.name);
+            USAGE
+        }
+    }
+}
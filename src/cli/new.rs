@@ -0,0 +1,67 @@
+use super::{Args, FAILURE, SUCCESS, USAGE, manifest::FILE_NAME};
+use std::{fs, path::Path};
+
+const MAIN: &str = "\
+// Entry point of {name}; run it with `sntvm run`.
+let greeting = \"hello from {name}\";
+print greeting;
+
+let count = 1;
+branch count {
+  let count = 2;
+}
+merge count;
+print count;
+";
+
+const TEST: &str = "\
+// `sntvm test` runs every script in this directory. Each `expect:` comment
+// gives the next line the script should print.
+let x = 1;
+branch x {
+  let x = 2;
+}
+merge x;
+print x; // expect: 2
+";
+
+const MANIFEST: &str = "\
+[package]
+entry = \"main.snt\"
+";
+
+fn scaffold(dir: &Path, name: &str) -> std::io::Result<()> {
+    fs::create_dir_all(dir.join("tests"))?;
+    fs::write(dir.join(FILE_NAME), MANIFEST)?;
+    fs::write(dir.join("main.snt"), MAIN.replace("{name}", name))?;
+    fs::write(dir.join("tests").join("branch.snt"), TEST)
+}
+
+pub fn main(args: &Args) -> i32 {
+    let path = match args.file() {
+        Ok(path) => path,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            return USAGE;
+        }
+    };
+    let dir = Path::new(path);
+    // Never write over an existing project or unrelated files.
+    if fs::read_dir(dir).is_ok_and(|mut entries| entries.next().is_some()) {
+        eprintln!("error: {} already exists and is not empty", dir.display());
+        return FAILURE;
+    }
+    let name = dir
+        .file_name()
+        .map_or(path.into(), |name| name.to_string_lossy());
+    if let Err(e) = scaffold(dir, &name) {
+        eprintln!("error: cannot create {}: {}", dir.display(), e);
+        return FAILURE;
+    }
+    println!("Created project `{}` in {}", name, dir.display());
+    println!();
+    println!("  cd {}", dir.display());
+    println!("  sntvm run     # runs main.snt");
+    println!("  sntvm test    # runs the scripts in tests/");
+    SUCCESS
+}
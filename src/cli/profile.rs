@@ -0,0 +1,118 @@
+use super::line_of;
+use sntvm::{InterpreterBuilder, NodeEvent, Span};
+use std::{
+    collections::HashMap,
+    fmt::Write,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+// One executed node. Branches contain the nodes of their body.
+struct Sample {
+    kind: &'static str,
+    span: Span,
+    start: Duration,
+    duration: Duration,
+}
+
+#[derive(Default)]
+struct Samples {
+    open: Vec<(NodeEvent, Instant)>,
+    done: Vec<Sample>,
+}
+
+// Timing collected through the interpreter's enter/exit hooks.
+pub struct Profile {
+    origin: Instant,
+    samples: Arc<Mutex<Samples>>,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Format {
+    // Time per source line, slowest first.
+    Text,
+    // Trace Event Format, for chrome://tracing and Perfetto.
+    Chrome,
+}
+
+impl Format {
+    pub fn from_name(name: &str) -> Option<Format> {
+        match name {
+            "text" => Some(Format::Text),
+            "chrome" => Some(Format::Chrome),
+            _ => None,
+        }
+    }
+}
+
+impl Profile {
+    pub fn install(builder: InterpreterBuilder) -> (InterpreterBuilder, Profile) {
+        let profile = Profile {
+            origin: Instant::now(),
+            samples: Arc::default(),
+        };
+        let (enter, exit) = (profile.samples.clone(), profile.samples.clone());
+        let origin = profile.origin;
+        let builder = builder.hooks(
+            move |event| enter.lock().unwrap().open.push((*event, Instant::now())),
+            move |event| {
+                let mut samples = exit.lock().unwrap();
+                if let Some((_, started)) = samples.open.pop() {
+                    samples.done.push(Sample {
+                        kind: event.kind,
+                        span: event.span,
+                        start: started - origin,
+                        duration: started.elapsed(),
+                    });
+                }
+            },
+        );
+        (builder, profile)
+    }
+
+    pub fn render(&self, format: Format, path: &str, src: &str) -> String {
+        let samples = self.samples.lock().unwrap();
+        match format {
+            Format::Text => text(&samples.done, path, src),
+            Format::Chrome => chrome(&samples.done, path, src),
+        }
+    }
+}
+
+fn text(samples: &[Sample], path: &str, src: &str) -> String {
+    // Only leaf statements, so a branch's time is not counted twice.
+    let mut lines: HashMap<usize, (u64, Duration)> = HashMap::new();
+    for sample in samples.iter().filter(|s| s.kind != "branch") {
+        let entry = lines.entry(line_of(src, sample.span.start)).or_default();
+        entry.0 += 1;
+        entry.1 += sample.duration;
+    }
+    let mut lines: Vec<_> = lines.into_iter().collect();
+    lines.sort_by(|a, b| b.1.1.cmp(&a.1.1).then(a.0.cmp(&b.0)));
+    let mut out = format!("{:>8} {:>12}  {}\n", "count", "time", "line");
+    for (line, (count, time)) in lines {
+        let _ = writeln!(out, "{:>8} {:>12?}  {}:{}", count, time, path, line);
+    }
+    out
+}
+
+fn chrome(samples: &[Sample], path: &str, src: &str) -> String {
+    let events: Vec<serde_json::Value> = samples
+        .iter()
+        .map(|s| {
+            let line = line_of(src, s.span.start);
+            let text = src[s.span.start..s.span.end].lines().next().unwrap_or("");
+            serde_json::json!({
+                "name": text.trim(),
+                "cat": s.kind,
+                "ph": "X",
+                "ts": s.start.as_secs_f64() * 1e6,
+                "dur": s.duration.as_secs_f64() * 1e6,
+                "pid": 1,
+                "tid": 1,
+                "args": { "file": path, "line": line },
+            })
+        })
+        .collect();
+    serde_json::json!({ "traceEvents": events, "displayTimeUnit": "ns" }).to_string()
+}
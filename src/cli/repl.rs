@@ -0,0 +1,198 @@
+use super::{Args, FAILURE, SUCCESS, USAGE, read_source, report};
+use rustyline::{DefaultEditor, error::ReadlineError};
+use sntvm::{Interpreter, Lexer, Program, Token};
+use std::{env, fs, path::PathBuf};
+
+const REPL_HELP: &str = "\
+:vars         show variables and their generations
+:branches     show branches waiting to be merged
+:save <file>  write the statements run so far as a script
+:load <file>  run a script in this session
+:reset        start over with an empty world
+:help         show this message
+:quit         exit (or Ctrl-D)";
+
+pub fn main(args: &Args) -> i32 {
+    if let Some(extra) = args.positional.first() {
+        eprintln!("error: unexpected argument `{}`", extra);
+        return USAGE;
+    }
+    let mut editor = match DefaultEditor::new() {
+        Ok(editor) => editor,
+        Err(e) => {
+            eprintln!("error: cannot start the line editor: {}", e);
+            return FAILURE;
+        }
+    };
+    let history = history_path();
+    if let Some(path) = &history {
+        // Missing on first use.
+        let _ = editor.load_history(path);
+    }
+    let status = session(&mut editor);
+    if let Some(path) = &history
+        && let Err(e) = editor.save_history(path)
+    {
+        eprintln!("warning: cannot save history to {}: {}", path.display(), e);
+    }
+    status
+}
+
+// `~/.sntvm_history`
+fn history_path() -> Option<PathBuf> {
+    let home = env::var_os("HOME").or_else(|| env::var_os("USERPROFILE"))?;
+    Some(PathBuf::from(home).join(".sntvm_history"))
+}
+
+fn session(editor: &mut DefaultEditor) -> i32 {
+    let mut interpreter = Interpreter::new();
+    let mut buffer = String::new();
+    // Source of everything that ran cleanly, for `:save`.
+    let mut transcript = String::new();
+    println!("sntvm {} (:help for commands)", env!("CARGO_PKG_VERSION"));
+    loop {
+        let line = match editor.readline(if buffer.is_empty() { ">>> " } else { "... " }) {
+            Ok(line) => line,
+            // Ctrl-C drops the statement being typed.
+            Err(ReadlineError::Interrupted) => {
+                buffer.clear();
+                continue;
+            }
+            Err(_) => {
+                println!();
+                break;
+            }
+        };
+        if !line.trim().is_empty() {
+            let _ = editor.add_history_entry(line.as_str());
+        }
+        if buffer.is_empty() {
+            match line.trim() {
+                "" => continue,
+                ":quit" | ":q" => break,
+                ":help" => {
+                    println!("{}", REPL_HELP);
+                    continue;
+                }
+                ":vars" => {
+                    show_vars(&interpreter);
+                    continue;
+                }
+                ":branches" => {
+                    show_branches(&interpreter);
+                    continue;
+                }
+                ":reset" => {
+                    interpreter = Interpreter::new();
+                    transcript.clear();
+                    continue;
+                }
+                cmd @ (":save" | ":load") => {
+                    eprintln!("usage: {} <file>", cmd);
+                    continue;
+                }
+                cmd if cmd.starts_with(":save ") || cmd.starts_with(":load ") => {
+                    let (cmd, path) = cmd.split_at(5);
+                    let path = path.trim();
+                    if cmd == ":save" {
+                        if let Err(e) = fs::write(path, &transcript) {
+                            eprintln!("error: cannot write {}: {}", path, e);
+                        }
+                    } else if let Some(src) = read_source(path)
+                        && execute(&mut interpreter, path, &src, false)
+                    {
+                        transcript.push_str(&src);
+                        if !src.ends_with('\n') {
+                            transcript.push('\n');
+                        }
+                    }
+                    if let Some(code) = interpreter.exit_code() {
+                        return code;
+                    }
+                    continue;
+                }
+                cmd if cmd.starts_with(':') => {
+                    eprintln!("unknown command {} (try :help)", cmd);
+                    continue;
+                }
+                _ => {}
+            }
+        }
+        buffer.push_str(&line);
+        buffer.push('\n');
+        // Keep reading while a `branch { ... }` body or a string is still
+        // open.
+        if incomplete(&buffer) {
+            continue;
+        }
+        let src = std::mem::take(&mut buffer);
+        if execute(&mut interpreter, "<repl>", &src, true) {
+            transcript.push_str(&src);
+        }
+        if let Some(code) = interpreter.exit_code() {
+            return code;
+        }
+    }
+    SUCCESS
+}
+
+// Runs `src`, reporting errors against `name`. With `echo`, shows the value
+// of the last statement. `true` if it parsed and ran without error.
+fn execute(interpreter: &mut Interpreter, name: &str, src: &str, echo: bool) -> bool {
+    let program = match Program::compile(src) {
+        Ok(program) => program,
+        Err(errors) => {
+            for e in &errors {
+                report(name, src, e);
+            }
+            return false;
+        }
+    };
+    // `print` already showed its value.
+    let echo = echo
+        && program
+            .ast()
+            .last()
+            .is_some_and(|node| node.kind() != "print");
+    match interpreter.eval(&program) {
+        Ok(Some(value)) if echo => println!("{}", value),
+        Ok(_) => {}
+        Err(e) => {
+            report(name, src, &e);
+            return false;
+        }
+    }
+    true
+}
+
+fn incomplete(src: &str) -> bool {
+    let mut lexer = Lexer::new(src);
+    let depth = lexer.by_ref().fold(0, |depth, token| match token {
+        Ok(Token::LBrace) => depth + 1,
+        Ok(Token::RBrace) => depth - 1,
+        _ => depth,
+    });
+    depth > 0 || lexer.string_at_end()
+}
+
+fn show_vars(interpreter: &Interpreter) {
+    let world = interpreter.world();
+    let mut vars: Vec<_> = world.iter().collect();
+    vars.sort_by_key(|(name, _)| *name);
+    for (name, value) in vars {
+        println!("{} = {} (generation {})", name, value, world.get_gen(name));
+    }
+}
+
+fn show_branches(interpreter: &Interpreter) {
+    let mut branches: Vec<_> = interpreter.branches().collect();
+    branches.sort_by_key(|b| b.variable());
+    for b in branches {
+        println!(
+            "{} (opened at generation {}, {} nested)",
+            b.variable(),
+            b.generation(),
+            b.nested().len()
+        );
+    }
+}
@@ -0,0 +1,398 @@
+use super::{
+    Args, FAILURE, SUCCESS, USAGE, line_of,
+    manifest::{FILE_NAME, Manifest},
+    profile::{self, Profile},
+    read_source, report,
+};
+use sntvm::{
+    Error, Interpreter, Io, Overflow, ParseError, Pause, Program, Resume, RuntimeErrorKind, Value,
+    World,
+};
+use std::{
+    collections::VecDeque,
+    fs,
+    io::{self, BufRead, Write},
+    path::{Path, PathBuf},
+    process,
+    time::Duration,
+};
+
+// `--stdin-from`: `input` reads the file's lines, output still goes to stdout.
+struct AnswersIo {
+    lines: VecDeque<String>,
+}
+
+impl Io for AnswersIo {
+    fn write_str(&mut self, text: &str) {
+        print!("{}", text);
+    }
+    fn read_line(&mut self) -> Option<String> {
+        self.lines.pop_front()
+    }
+    fn flush(&mut self) {
+        let _ = io::stdout().flush();
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Dump {
+    None,
+    Debug,
+    Json,
+    Diff,
+}
+
+// One line per variable that changed, was added (`+`) or removed (`-`).
+fn print_diff(before: &World, after: &World) {
+    let mut names: Vec<&str> = before.iter().chain(after.iter()).map(|(k, _)| k).collect();
+    names.sort();
+    names.dedup();
+    for name in names {
+        let generation = |w: &World| w.get_gen(name);
+        match (before.get(name), after.get(name)) {
+            (None, Some(new)) => println!("+ {} = {:?}", name, new),
+            (Some(_), None) => println!("- {}", name),
+            (Some(old), Some(new)) if old != new || generation(before) != generation(after) => {
+                println!(
+                    "~ {}: {:?} -> {:?} (generation {} -> {})",
+                    name,
+                    old,
+                    new,
+                    generation(before),
+                    generation(after)
+                )
+            }
+            _ => {}
+        }
+    }
+}
+
+// `--step`: before each statement, show it with the variable it touches and
+// wait for Enter. Drawn on stderr so the script's own output stays on stdout.
+fn step_through(src: String) -> impl FnMut(&Pause) -> Resume + Send + 'static {
+    let mut interactive = true;
+    move |pause| {
+        if !interactive {
+            return Resume::Continue;
+        }
+        let span = pause.node.span();
+        let text = src[span.start..span.end].lines().next().unwrap_or("");
+        eprintln!(
+            "-- step {}, line {} --",
+            pause.step,
+            line_of(&src, span.start)
+        );
+        eprintln!("  {}", text.trim());
+        if let Some(name) = pause.node.variable() {
+            let generation = pause.world.get_gen(name);
+            match pause.world.get(name) {
+                Some(value) => eprintln!("  {} = {:?} (generation {})", name, value, generation),
+                None => eprintln!("  {} is undefined", name),
+            }
+            if let Some(branch) = pause.branch(name) {
+                eprintln!(
+                    "  branch on {} waiting to merge (opened at generation {})",
+                    name,
+                    branch.generation()
+                );
+            }
+        }
+        for outer in pause.stack.iter().rev() {
+            eprintln!("  inside the branch at line {}", line_of(&src, outer.start));
+        }
+        eprint!("[Enter] next, q quit: ");
+        let _ = io::stderr().flush();
+        let mut line = String::new();
+        match io::stdin().lock().read_line(&mut line) {
+            Ok(0) | Err(_) => {
+                // No terminal to answer: run the rest without pausing.
+                eprintln!();
+                interactive = false;
+                Resume::Continue
+            }
+            Ok(_) if line.trim() == "q" => Resume::Stop,
+            Ok(_) => Resume::Continue,
+        }
+    }
+}
+
+// `-` is standard input.
+fn open_stream(path: &str) -> Option<Box<dyn BufRead>> {
+    if path == "-" {
+        return Some(Box::new(io::stdin().lock()));
+    }
+    match fs::File::open(path) {
+        Ok(file) => Some(Box::new(io::BufReader::new(file))),
+        Err(e) => {
+            eprintln!("error: cannot read {}: {}", path, e);
+            None
+        }
+    }
+}
+
+// Cargo features this binary was built with. A program compiled by one build
+// may not decode, or may not mean the same, in a build with other features.
+const FEATURES: [(&str, bool); 12] = [
+    ("fast-hash", cfg!(feature = "fast-hash")),
+    ("persistent", cfg!(feature = "persistent")),
+    ("cdylib", cfg!(feature = "cdylib")),
+    ("wasm", cfg!(feature = "wasm")),
+    ("tracing", cfg!(feature = "tracing")),
+    ("uuid", cfg!(feature = "uuid")),
+    ("hashing", cfg!(feature = "hashing")),
+    ("net", cfg!(feature = "net")),
+    ("decimal", cfg!(feature = "decimal")),
+    ("process", cfg!(feature = "process")),
+    ("rc", cfg!(feature = "rc")),
+    ("parallel", cfg!(feature = "parallel")),
+];
+
+// `--cache-dir`: compiled programs are kept under a hash of the sntvm version,
+// its features and the source, so running an unchanged script again skips
+// lexing and parsing. FNV-1a rather than `DefaultHasher`, which may change
+// between builds. The hash only picks the file: two sources can share it,
+// so each file also holds its source (see `cache_entry`).
+fn cache_path(dir: &str, code: &str) -> PathBuf {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    let version = env!("CARGO_PKG_VERSION").bytes().chain([0]);
+    let features = FEATURES
+        .iter()
+        .filter(|(_, enabled)| *enabled)
+        .flat_map(|(name, _)| name.bytes().chain([0]));
+    for byte in version.chain(features).chain([0]).chain(code.bytes()) {
+        hash = (hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3);
+    }
+    Path::new(dir).join(format!("{:016x}.sntc", hash))
+}
+
+// A cache file is the source's byte length (8 bytes, little-endian), the
+// source, then the program. The program is only used when the source matches
+// `code` exactly, so a colliding file makes for a cache miss, never for
+// another script's program.
+fn cache_entry(code: &str, program: Vec<u8>) -> Vec<u8> {
+    let mut entry = Vec::with_capacity(8 + code.len() + program.len());
+    entry.extend((code.len() as u64).to_le_bytes());
+    entry.extend(code.as_bytes());
+    entry.extend(program);
+    entry
+}
+
+fn cached_program(entry: &[u8], code: &str) -> Option<Program> {
+    let (len, rest) = entry.split_first_chunk::<8>()?;
+    let len = usize::try_from(u64::from_le_bytes(*len)).ok()?;
+    let (source, program) = rest.split_at_checked(len)?;
+    if source != code.as_bytes() {
+        return None;
+    }
+    Program::from_bytes(program)
+}
+
+fn compile_cached(dir: Option<&str>, code: &str) -> Result<Program, Vec<ParseError>> {
+    let Some(dir) = dir else {
+        return Program::compile(code);
+    };
+    let path = cache_path(dir, code);
+    if let Some(program) = fs::read(&path).ok().and_then(|b| cached_program(&b, code)) {
+        return Ok(program);
+    }
+    let program = Program::compile(code)?;
+    // Written aside and renamed, so a concurrent run never reads half a file.
+    // A cache that cannot be written only costs the next run a parse.
+    if let Some(bytes) = program.to_bytes() {
+        let partial = path.with_extension(format!("{}.tmp", process::id()));
+        let written = fs::create_dir_all(dir)
+            .and_then(|_| fs::write(&partial, cache_entry(code, bytes)))
+            .and_then(|_| fs::rename(&partial, &path));
+        if written.is_err() {
+            let _ = fs::remove_file(&partial);
+        }
+    }
+    Ok(program)
+}
+
+pub fn main(args: &Args) -> i32 {
+    // Everything after the file (and after `--`) is for the script. Without a
+    // file, the entry point and options come from the project's snt.toml.
+    let (manifest, path, script_args) = match args.positional.split_first() {
+        Some((path, script_args)) => (None, path.clone(), script_args),
+        None => match Manifest::discover() {
+            Ok(Some(manifest)) => {
+                let path = manifest.entry().display().to_string();
+                (Some(manifest), path, &[][..])
+            }
+            Ok(None) => {
+                eprintln!("error: missing <file> argument and no {} found", FILE_NAME);
+                return USAGE;
+            }
+            Err(e) => {
+                eprintln!("error: {}", e);
+                return FAILURE;
+            }
+        },
+    };
+    let options = manifest.as_ref().map(|m| &m.run);
+    let dump_world = args
+        .value("dump-world")
+        .or_else(|| options?.dump_world.as_deref());
+    let dump = match dump_world {
+        None | Some("none") => Dump::None,
+        Some("debug") => Dump::Debug,
+        Some("json") => Dump::Json,
+        Some("diff") => Dump::Diff,
+        Some(other) => {
+            eprintln!(
+                "error: `--dump-world` expects none, debug, json or diff, got `{}`",
+                other
+            );
+            return USAGE;
+        }
+    };
+    let profile_format = match args.value("profile-format").map(profile::Format::from_name) {
+        None => profile::Format::Text,
+        Some(Some(format)) => format,
+        Some(None) => {
+            eprintln!("error: `--profile-format` expects text or chrome");
+            return USAGE;
+        }
+    };
+    let seed = match args.value("seed").map(str::parse::<u64>) {
+        None => options.and_then(|o| o.seed),
+        Some(Ok(seed)) => Some(seed),
+        Some(Err(_)) => {
+            eprintln!("error: `--seed` expects a non-negative integer");
+            return USAGE;
+        }
+    };
+    let overflow = match args
+        .value("overflow")
+        .or_else(|| options?.overflow.as_deref())
+    {
+        None => None,
+        Some(name) => match Overflow::from_name(name) {
+            Some(policy) => Some(policy),
+            None => {
+                eprintln!(
+                    "error: `--overflow` expects error, wrap, saturate or float, got `{}`",
+                    name
+                );
+                return USAGE;
+            }
+        },
+    };
+    let script_args: Vec<Value> = script_args
+        .iter()
+        .chain(&args.rest)
+        .map(|arg| Value::from(arg.as_str()))
+        .collect();
+    let path = path.as_str();
+    // A streamed script is never held whole, so reports about it name the
+    // file but show no source.
+    let stream = args.flag("stream");
+    if stream
+        && (args.flag("step")
+            || args.value("profile").is_some()
+            || args.value("cache-dir").is_some())
+    {
+        eprintln!(
+            "error: `--stream` cannot be combined with `--step`, `--profile` or `--cache-dir`"
+        );
+        return USAGE;
+    }
+    let (code, program) = if stream {
+        (String::new(), None)
+    } else {
+        let Some(code) = read_source(path) else {
+            return FAILURE;
+        };
+        match compile_cached(args.value("cache-dir"), &code) {
+            Ok(program) => (code, Some(program)),
+            Err(errors) => {
+                for e in &errors {
+                    report(path, &code, e);
+                }
+                return FAILURE;
+            }
+        }
+    };
+    let mut builder = Interpreter::builder()
+        .var("argc", script_args.len() as i32)
+        .var("args", script_args);
+    if let Some(steps) = options.and_then(|o| o.fuel) {
+        builder = builder.fuel(steps);
+    }
+    if let Some(seed) = seed {
+        builder = builder.seed(seed);
+    }
+    if let Some(policy) = overflow {
+        builder = builder.overflow(policy);
+    }
+    if args.flag("sandbox") || options.is_some_and(|o| o.sandbox) {
+        builder = builder.sandbox(true);
+    }
+    if args.flag("strict") || options.is_some_and(|o| o.strict) {
+        builder = builder.strict(true);
+    }
+    if let Some(ms) = options.and_then(|o| o.timeout_ms) {
+        builder = builder.timeout(Duration::from_millis(ms));
+    }
+    if let Some(answers) = args.value("stdin-from") {
+        let Some(text) = read_source(answers) else {
+            return FAILURE;
+        };
+        let lines = text.lines().map(String::from).collect();
+        builder = builder.io(AnswersIo { lines }).require_input(true);
+    }
+    if args.flag("step") {
+        builder = builder.stepper(step_through(code.clone()));
+    }
+    let mut profile = None;
+    if args.value("profile").is_some() {
+        let (profiled, handle) = Profile::install(builder);
+        builder = profiled;
+        profile = Some(handle);
+    }
+    let mut interpreter = builder.build();
+
+    let before = interpreter.world().snapshot();
+    if dump == Dump::Debug {
+        println!("Before execution: {:?}", interpreter.world());
+    }
+    let result = match &program {
+        Some(program) => interpreter.run(program).map_err(Error::Runtime),
+        None => {
+            let Some(reader) = open_stream(path) else {
+                return FAILURE;
+            };
+            interpreter.run_reader(reader)
+        }
+    };
+    // Written even when the run fails: that is often when it is wanted.
+    if let (Some(profile), Some(out)) = (profile, args.value("profile"))
+        && let Err(e) = fs::write(out, profile.render(profile_format, path, &code))
+    {
+        eprintln!("error: cannot write {}: {}", out, e);
+        return FAILURE;
+    }
+    match result {
+        Ok(()) => {}
+        Err(Error::Parse(errors)) => {
+            for e in &errors {
+                report(path, &code, e);
+            }
+            return FAILURE;
+        }
+        Err(Error::Runtime(e)) => {
+            if e.kind != RuntimeErrorKind::Stopped {
+                report(path, &code, &e);
+            }
+            return FAILURE;
+        }
+    }
+    match dump {
+        Dump::None => {}
+        Dump::Debug => println!("After execution: {:?}", interpreter.world()),
+        Dump::Json => println!("{}", interpreter.world().to_json()),
+        Dump::Diff => print_diff(&before, interpreter.world()),
+    }
+    interpreter.exit_code().unwrap_or(SUCCESS)
+}
@@ -0,0 +1,172 @@
+use super::{Args, FAILURE, SUCCESS, manifest::Manifest};
+use sntvm::{Interpreter, MemoryIo, Program, Report, formatter};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+// A script passes when it runs without error (a failing `assert` is one) or
+// `exit` code and its output matches every expectation it has:
+//   - `// expect: <line>` comments (on their own line or trailing a
+//     statement), in order, give the expected output lines;
+//   - `name.out` next to `name.snt` gives the expected output verbatim;
+//   - `name.json` gives the expected final world (as `World::to_json`).
+// A script with no expectations only has to run cleanly.
+fn collect(path: &Path, files: &mut Vec<PathBuf>) {
+    if path.is_dir() {
+        let Ok(entries) = fs::read_dir(path) else {
+            eprintln!("error: cannot read directory {}", path.display());
+            return;
+        };
+        let mut entries: Vec<PathBuf> = entries.filter_map(|e| Some(e.ok()?.path())).collect();
+        entries.sort();
+        for entry in entries {
+            if entry.is_dir() || entry.extension().is_some_and(|ext| ext == "snt") {
+                collect(&entry, files);
+            }
+        }
+    } else {
+        files.push(path.to_path_buf());
+    }
+}
+
+// The text of each `// expect:` comment, one line per comment.
+fn inline_expectations(src: &str) -> Option<String> {
+    let (mut lines, mut rest) = (Vec::new(), src);
+    while let Some(start) = formatter::comment_start(rest) {
+        let comment = &rest[start..];
+        let end = comment.find('\n').unwrap_or(comment.len());
+        if let Some(text) = comment[..end]
+            .trim_end_matches('\r')
+            .strip_prefix("// expect:")
+        {
+            lines.push(text.strip_prefix(' ').unwrap_or(text));
+        }
+        rest = &comment[end..];
+    }
+    if lines.is_empty() {
+        return None;
+    }
+    Some(lines.iter().map(|line| format!("{}\n", line)).collect())
+}
+
+// Line diff of `expected` against `actual` via longest common subsequence.
+fn diff(expected: &str, actual: &str) -> String {
+    let a: Vec<&str> = expected.lines().collect();
+    let b: Vec<&str> = actual.lines().collect();
+    let mut lcs = vec![vec![0usize; b.len() + 1]; a.len() + 1];
+    for i in (0..a.len()).rev() {
+        for j in (0..b.len()).rev() {
+            lcs[i][j] = if a[i] == b[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+    let (mut i, mut j, mut out) = (0, 0, String::new());
+    while i < a.len() || j < b.len() {
+        if i < a.len() && j < b.len() && a[i] == b[j] {
+            out.push_str(&format!("   {}\n", a[i]));
+            i += 1;
+            j += 1;
+        } else if i < a.len() && (j == b.len() || lcs[i + 1][j] >= lcs[i][j + 1]) {
+            out.push_str(&format!("  -{}\n", a[i]));
+            i += 1;
+        } else {
+            out.push_str(&format!("  +{}\n", b[j]));
+            j += 1;
+        }
+    }
+    out
+}
+
+// Runs one script; `Err` holds the failure explanation.
+fn run_test(path: &Path, strict: bool) -> Result<(), String> {
+    let name = path.display().to_string();
+    let src = fs::read_to_string(path).map_err(|e| format!("cannot read: {}", e))?;
+    let program = Program::compile(&src).map_err(|errors| {
+        errors
+            .iter()
+            .map(|e| Report::from(e).render(&name, &src))
+            .collect::<String>()
+    })?;
+    let io = MemoryIo::new();
+    let mut interpreter = Interpreter::builder().io(io.clone()).strict(strict).build();
+    interpreter
+        .run(&program)
+        .map_err(|e| Report::from(&e).render(&name, &src))?;
+    if let Some(code) = interpreter.exit_code().filter(|&code| code != 0) {
+        return Err(format!("exited with code {}\n", code));
+    }
+
+    let output = io.take_output();
+    let mut failures = String::new();
+    let out_file = fs::read_to_string(path.with_extension("out")).ok();
+    for expected in inline_expectations(&src).into_iter().chain(out_file) {
+        if expected != output {
+            failures.push_str("output differs (-expected +actual):\n");
+            failures.push_str(&diff(&expected, &output));
+        }
+    }
+    if let Ok(json) = fs::read_to_string(path.with_extension("json")) {
+        let expected: serde_json::Value = serde_json::from_str(&json).map_err(|e| {
+            format!(
+                "{}: invalid JSON: {}\n",
+                path.with_extension("json").display(),
+                e
+            )
+        })?;
+        let actual = interpreter.world().to_json();
+        if expected != actual {
+            let pretty =
+                |v: &serde_json::Value| serde_json::to_string_pretty(v).unwrap_or_default();
+            failures.push_str("final world differs (-expected +actual):\n");
+            failures.push_str(&diff(&pretty(&expected), &pretty(&actual)));
+        }
+    }
+    if failures.is_empty() {
+        Ok(())
+    } else {
+        Err(failures)
+    }
+}
+
+pub fn main(args: &Args) -> i32 {
+    let roots = if args.positional.is_empty() {
+        match Manifest::discover() {
+            Ok(Some(manifest)) => vec![manifest.root.join("tests").display().to_string()],
+            Ok(None) => vec![".".to_string()],
+            Err(e) => {
+                eprintln!("error: {}", e);
+                return FAILURE;
+            }
+        }
+    } else {
+        args.positional.clone()
+    };
+    let mut files = Vec::new();
+    for root in &roots {
+        collect(Path::new(root), &mut files);
+    }
+    let mut failed = Vec::new();
+    for file in &files {
+        match run_test(file, !args.flag("lenient")) {
+            Ok(()) => println!("test {} ... ok", file.display()),
+            Err(why) => {
+                println!("test {} ... FAILED", file.display());
+                failed.push((file, why));
+            }
+        }
+    }
+    for (file, why) in &failed {
+        println!("\n---- {} ----\n{}", file.display(), why.trim_end());
+    }
+    println!(
+        "\ntest result: {}. {} passed; {} failed",
+        if failed.is_empty() { "ok" } else { "FAILED" },
+        files.len() - failed.len(),
+        failed.len()
+    );
+    if failed.is_empty() { SUCCESS } else { FAILURE }
+}
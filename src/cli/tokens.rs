@@ -0,0 +1,44 @@
+use super::{Args, FAILURE, SUCCESS, USAGE, read_source};
+use sntvm::Lexer;
+
+// 1-based line and column (in chars) of a byte offset.
+fn line_col(src: &str, offset: usize) -> (usize, usize) {
+    let before = &src[..offset];
+    let line = before.matches('\n').count() + 1;
+    let col = before.rsplit('\n').next().unwrap_or("").chars().count() + 1;
+    (line, col)
+}
+
+// One line per token: `line:col  start..end  Token`. Lex errors are listed
+// in-line where they occur, and make the exit code non-zero.
+pub fn main(args: &Args) -> i32 {
+    let path = match args.file() {
+        Ok(path) => path,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            return USAGE;
+        }
+    };
+    let Some(src) = read_source(path) else {
+        return FAILURE;
+    };
+    let mut status = SUCCESS;
+    let mut lexer = Lexer::new(&src);
+    while let Some(result) = lexer.next_spanned() {
+        match result {
+            Ok((token, span)) => {
+                let (line, col) = line_col(&src, span.start);
+                let pos = format!("{}:{}", line, col);
+                let range = format!("{}..{}", span.start, span.end);
+                println!("{:<8}{:<12}{:?}", pos, range, token);
+            }
+            Err(e) => {
+                let (line, col) = line_col(&src, e.offset);
+                let pos = format!("{}:{}", line, col);
+                println!("{:<8}{:<12}error: {}", pos, e.offset, e.message);
+                status = FAILURE;
+            }
+        }
+    }
+    status
+}
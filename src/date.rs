@@ -0,0 +1,160 @@
+// ===== Dates =====
+// Times are whole seconds since 1970-01-01T00:00:00Z and durations are whole
+// seconds, so both fit in plain integers. Everything is UTC on the proleptic
+// Gregorian calendar; there are no time zones or leap seconds.
+
+// Used by `datefmt` without a format, and tried first by `dateparse`.
+pub const ISO_8601: &str = "%Y-%m-%dT%H:%M:%SZ";
+
+// What `dateparse` accepts without an explicit format.
+pub const PARSE_FORMATS: &[&str] = &[
+    ISO_8601,
+    "%Y-%m-%dT%H:%M:%S",
+    "%Y-%m-%d %H:%M:%S",
+    "%Y-%m-%d",
+];
+
+// Howard Hinnant's `days_from_civil`: days since 1970-01-01.
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let yoe = year - era * 400;
+    let month = month as i64;
+    let doy = (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + day as i64 - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146097 + doe - 719468
+}
+
+// The inverse of `days_from_civil`.
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+fn days_in_month(year: i64, month: u32) -> u32 {
+    match month {
+        2 if year % 4 == 0 && (year % 100 != 0 || year % 400 == 0) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+// `%Y` `%m` `%d` `%H` `%M` `%S` and `%%`, as in strftime. Anything else is
+// copied through.
+pub fn format(secs: i64, fmt: &str) -> String {
+    let (year, month, day) = civil_from_days(secs.div_euclid(86400));
+    let time = secs.rem_euclid(86400);
+    let mut out = String::new();
+    let mut chars = fmt.chars();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('Y') => out.push_str(&format!("{:04}", year)),
+            Some('m') => out.push_str(&format!("{:02}", month)),
+            Some('d') => out.push_str(&format!("{:02}", day)),
+            Some('H') => out.push_str(&format!("{:02}", time / 3600)),
+            Some('M') => out.push_str(&format!("{:02}", time / 60 % 60)),
+            Some('S') => out.push_str(&format!("{:02}", time % 60)),
+            Some('%') => out.push('%'),
+            Some(other) => {
+                out.push('%');
+                out.push(other);
+            }
+            None => out.push('%'),
+        }
+    }
+    out
+}
+
+// The inverse of `format`: `%Y` reads four digits and the others two. The
+// whole text must match; fields the format leaves out are zero (or the 1st).
+pub fn parse(text: &str, fmt: &str) -> Option<i64> {
+    let (mut year, mut month, mut day) = (1970, 1, 1);
+    let (mut hour, mut minute, mut second) = (0, 0, 0);
+    let mut text = text;
+    let mut chars = fmt.chars();
+    while let Some(c) = chars.next() {
+        let spec = match c {
+            '%' => chars.next()?,
+            c => {
+                text = text.strip_prefix(c)?;
+                continue;
+            }
+        };
+        let width = match spec {
+            'Y' => 4,
+            'm' | 'd' | 'H' | 'M' | 'S' => 2,
+            '%' => {
+                text = text.strip_prefix('%')?;
+                continue;
+            }
+            _ => return None,
+        };
+        let digits = text
+            .get(..width)
+            .filter(|d| d.bytes().all(|b| b.is_ascii_digit()))?;
+        text = &text[width..];
+        let n: u32 = digits.parse().ok()?;
+        match spec {
+            'Y' => year = n as i64,
+            'm' => month = n,
+            'd' => day = n,
+            'H' => hour = n,
+            'M' => minute = n,
+            _ => second = n,
+        }
+    }
+    if !text.is_empty()
+        || !(1..=12).contains(&month)
+        || day == 0
+        || day > days_in_month(year, month)
+        || hour > 23
+        || minute > 59
+        || second > 59
+    {
+        return None;
+    }
+    let time = hour as i64 * 3600 + minute as i64 * 60 + second as i64;
+    Some(days_from_civil(year, month, day) * 86400 + time)
+}
+
+// `90s`, `15m`, `1h30m`, `2d`, `-1d12h`: integers each followed by a unit
+// of days, hours, minutes or seconds.
+pub fn parse_duration(text: &str) -> Option<i64> {
+    let (sign, mut rest) = match text.strip_prefix('-') {
+        Some(rest) => (-1, rest),
+        None => (1, text),
+    };
+    if rest.is_empty() {
+        return None;
+    }
+    let mut total: i64 = 0;
+    while !rest.is_empty() {
+        let digits = rest
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(rest.len());
+        let n: i64 = rest[..digits].parse().ok()?;
+        let unit = match rest[digits..].chars().next()? {
+            'd' => 86400,
+            'h' => 3600,
+            'm' => 60,
+            's' => 1,
+            _ => return None,
+        };
+        total = total.checked_add(n.checked_mul(unit)?)?;
+        rest = &rest[digits + 1..];
+    }
+    Some(sign * total)
+}
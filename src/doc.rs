@@ -0,0 +1,70 @@
+use crate::ast::ASTNode;
+use crate::formatter::format_ast;
+use crate::lexer::Span;
+use crate::parser::ParseError;
+use crate::vm::Program;
+
+// ===== Doc comments =====
+// A run of `///` lines directly above a top-level `let` documents it, whether
+// it binds a value or the result of a call. A blank line or an ordinary
+// comment in between breaks the link.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DocItem {
+    pub name: String,
+    // The declaration in canonical layout, e.g. `let x = 1;`.
+    pub declaration: String,
+    pub docs: String,
+    pub span: Span,
+}
+
+pub fn extract(src: &str) -> Result<Vec<DocItem>, Vec<ParseError>> {
+    let program = Program::compile(src)?;
+    let mut items = Vec::new();
+    let mut prev_end = None;
+    for node in program.ast() {
+        let span = node.span();
+        let gap = &src[prev_end.unwrap_or(0)..span.start];
+        let mut lines: Vec<&str> = gap.split('\n').collect();
+        // Whatever precedes the `let` on its own line, and the rest of the
+        // previous statement's line, are never docs.
+        lines.pop();
+        if prev_end.is_some() && !lines.is_empty() {
+            lines.remove(0);
+        }
+        prev_end = Some(span.end);
+        let name = match node {
+            ASTNode::Let { name, .. } => name,
+            ASTNode::Call {
+                target: Some(name), ..
+            } => name,
+            _ => continue,
+        };
+        let mut docs: Vec<&str> = lines
+            .iter()
+            .rev()
+            .map_while(|line| doc_line(line))
+            .collect();
+        if docs.is_empty() {
+            continue;
+        }
+        docs.reverse();
+        items.push(DocItem {
+            name: name.to_string(),
+            declaration: format_ast(std::slice::from_ref(node))
+                .trim_end()
+                .to_string(),
+            docs: docs.join("\n"),
+            span,
+        });
+    }
+    Ok(items)
+}
+
+// The text of a `///` line, without the marker and one following space.
+fn doc_line(line: &str) -> Option<&str> {
+    let text = line.trim().strip_prefix("///")?;
+    if text.starts_with('/') {
+        return None;
+    }
+    Some(text.strip_prefix(' ').unwrap_or(text))
+}
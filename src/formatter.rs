@@ -0,0 +1,353 @@
+use crate::ast::{ASTNode, Argument, InputType, PrintTarget};
+use crate::lexer::{RAW_QUOTES, Span, raw_string_len};
+use crate::parser::ParseError;
+use crate::value::Value;
+use crate::vm::Program;
+use std::{fmt::Write, ops::Range};
+
+// ===== Formatter =====
+// Canonical layout: one statement per line, each ending in `;`, branch bodies
+// indented by two spaces. `//` comments between statements and blank lines
+// (runs of them collapse to one) are kept; everything else comes from the AST.
+const INDENT: &str = "  ";
+
+pub fn format_source(src: &str) -> Result<String, Vec<ParseError>> {
+    let program = Program::compile(src)?;
+    if let Some(e) = comment_inside_statement(program.ast(), src) {
+        return Err(vec![e]);
+    }
+    // A `#!` line stays first, as the open line the rest follows.
+    let shebang = match src.starts_with("#!") {
+        true => src.find('\n').unwrap_or(src.len()),
+        false => 0,
+    };
+    let mut out = src[..shebang].trim_end().to_string();
+    write_block(&mut out, program.ast(), 0, src, shebang..src.len());
+    Ok(out)
+}
+
+// Formats an AST that has no source text behind it (built or rewritten by a
+// host), so there are no comments or blank lines to keep.
+pub fn format_ast(ast: &[ASTNode]) -> String {
+    let mut out = String::new();
+    write_block(&mut out, ast, 0, "", 0..0);
+    out
+}
+
+// Byte offset of a `//` comment in `text`, skipping string and character
+// literals.
+pub fn comment_start(text: &str) -> Option<usize> {
+    let mut in_string = false;
+    let mut chars = text.char_indices();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '"' if !in_string && text[i..].starts_with(RAW_QUOTES) => {
+                let end = i + 3 + raw_string_len(&text[i + 3..])? + 3;
+                while chars.offset() < end {
+                    chars.next();
+                }
+            }
+            '"' => in_string = !in_string,
+            '\'' if !in_string => {
+                chars.next();
+                chars.next();
+            }
+            '/' if !in_string && text[i..].starts_with("//") => return Some(i),
+            _ => {}
+        }
+    }
+    None
+}
+
+// Where a branch body starts: just past its `{`.
+fn body_start(src: &str, span: Span) -> Option<usize> {
+    let header = src.get(span.start..span.end)?;
+    Some(span.start + header.find('{')? + 1)
+}
+
+// Comments can only be kept where a statement could go.
+fn comment_inside_statement(ast: &[ASTNode], src: &str) -> Option<ParseError> {
+    for node in ast {
+        let span = node.span();
+        let head_end = match node {
+            ASTNode::Branch { .. } => body_start(src, span).unwrap_or(span.end),
+            _ => span.end,
+        };
+        if let Some(i) = comment_start(&src[span.start..head_end]) {
+            let at = span.start + i;
+            return Some(ParseError {
+                message: "cannot format a comment inside a statement".to_string(),
+                span: Span::new(at, at + 2),
+                hint: Some("move the comment to its own line"),
+            });
+        }
+        if let ASTNode::Branch { body, .. } = node
+            && let Some(e) = comment_inside_statement(body, src)
+        {
+            return Some(e);
+        }
+    }
+    None
+}
+
+// Writes `nodes`, keeping the comments and blank lines found in `range` of
+// the source around them. On entry the current output line is still open
+// (a statement or `branch x {`), unless nothing has been written yet.
+fn write_block(out: &mut String, nodes: &[ASTNode], depth: usize, src: &str, range: Range<usize>) {
+    let mut pos = range.start;
+    for (i, node) in nodes.iter().enumerate() {
+        let gap = src.get(pos..node.span().start).unwrap_or("");
+        // At the top, a blank line may also separate a `#!` line.
+        let blank_ok = i > 0 || (depth == 0 && !out.is_empty());
+        write_gap(out, gap, depth, blank_ok, true);
+        out.push_str(&INDENT.repeat(depth));
+        write_node(out, node, depth, src);
+        pos = node.span().end;
+    }
+    let gap = src.get(pos..range.end).unwrap_or("");
+    write_gap(out, gap, depth, !nodes.is_empty(), false);
+}
+
+// Source between two statements: a comment on the first line trails the
+// previous statement, later ones get lines of their own.
+fn write_gap(out: &mut String, gap: &str, depth: usize, mut blank_ok: bool, before_node: bool) {
+    let mut lines = gap.split('\n');
+    let first = lines.next().unwrap_or("");
+    if let Some(i) = comment_start(first) {
+        if !out.is_empty() {
+            out.push(' ');
+        }
+        out.push_str(first[i..].trim_end());
+        blank_ok = true;
+    }
+    if !out.is_empty() {
+        out.push('\n');
+    }
+    let rest: Vec<&str> = lines.collect();
+    let mut pending_blank = false;
+    for (i, line) in rest.iter().enumerate() {
+        if let Some(c) = comment_start(line) {
+            if pending_blank && blank_ok {
+                out.push('\n');
+            }
+            pending_blank = false;
+            blank_ok = true;
+            let _ = writeln!(out, "{}{}", INDENT.repeat(depth), line[c..].trim_end());
+        } else if line.trim().is_empty() && i + 1 < rest.len() {
+            pending_blank = true;
+        }
+    }
+    if pending_blank && blank_ok && before_node {
+        out.push('\n');
+    }
+}
+
+fn write_node(out: &mut String, node: &ASTNode, depth: usize, src: &str) {
+    match node {
+        ASTNode::Let { name, value, .. } => {
+            let _ = write!(out, "let {} = ", name);
+            write_value(out, value);
+            out.push(';');
+        }
+        ASTNode::Branch {
+            variable,
+            body,
+            span,
+        } => {
+            let _ = write!(out, "branch {} {{", variable);
+            let inner = match body_start(src, *span) {
+                Some(start) => start..span.end - 1,
+                None => 0..0,
+            };
+            let gap = src.get(inner.clone()).unwrap_or("");
+            if body.is_empty() && comment_start(gap).is_none() {
+                out.push('}');
+            } else {
+                write_block(out, body, depth + 1, src, inner);
+                out.push_str(&INDENT.repeat(depth));
+                out.push('}');
+            }
+        }
+        ASTNode::Exit { code, .. } => {
+            let _ = write!(out, "exit {};", code);
+        }
+        ASTNode::Merge { variable, .. } => {
+            let _ = write!(out, "merge {};", variable);
+        }
+        ASTNode::Assert {
+            condition, message, ..
+        } => {
+            out.push_str("assert ");
+            match condition {
+                Argument::Variable(name) => out.push_str(name),
+                Argument::Value(value) => write_value(out, value),
+            }
+            if let Some(message) = message {
+                out.push_str(", ");
+                write_string(out, message);
+            }
+            out.push(';');
+        }
+        ASTNode::Call {
+            target,
+            function,
+            args,
+            ..
+        } => {
+            if let Some(target) = target {
+                let _ = write!(out, "let {} = ", target);
+            }
+            let _ = write!(out, "{}(", function);
+            for (i, arg) in args.iter().enumerate() {
+                if i > 0 {
+                    out.push_str(", ");
+                }
+                match arg {
+                    Argument::Variable(name) => out.push_str(name),
+                    Argument::Value(value) => write_value(out, value),
+                }
+            }
+            out.push_str(");");
+        }
+        ASTNode::Print { target, .. } => {
+            out.push_str("print ");
+            match target {
+                PrintTarget::Variable(name) => out.push_str(name),
+                PrintTarget::Value(value) => write_value(out, value),
+            }
+            out.push(';');
+        }
+        ASTNode::Input {
+            prompt,
+            input_type,
+            variable,
+            ..
+        } => {
+            out.push_str("input ");
+            if *input_type != InputType::Str {
+                let _ = write!(out, "{} ", input_type.name());
+            }
+            write_string(out, prompt.as_deref().unwrap_or(""));
+            let _ = write!(out, " {};", variable);
+        }
+        ASTNode::ListPush {
+            variable, value, ..
+        } => {
+            let _ = write!(out, "listpush {} ", variable);
+            write_value(out, value);
+            out.push(';');
+        }
+        ASTNode::ListPushFront {
+            variable, value, ..
+        } => {
+            let _ = write!(out, "listpushfront {} ", variable);
+            write_value(out, value);
+            out.push(';');
+        }
+        ASTNode::ListPopFront {
+            variable, target, ..
+        } => {
+            let _ = write!(out, "listpopfront {} {};", variable, target);
+        }
+        ASTNode::ListPopBack {
+            variable, target, ..
+        } => {
+            let _ = write!(out, "listpopback {} {};", variable, target);
+        }
+        ASTNode::SetInsert {
+            variable, value, ..
+        } => {
+            let _ = write!(out, "setinsert {} ", variable);
+            write_value(out, value);
+            out.push(';');
+        }
+    }
+}
+
+// A string with a `"` of its own needs raw quotes. One holding `"""` cannot
+// be written either way.
+fn write_string(out: &mut String, s: &str) {
+    if s.contains('"') {
+        let _ = write!(out, "{0}{1}{0}", RAW_QUOTES, s);
+    } else {
+        let _ = write!(out, "\"{}\"", s);
+    }
+}
+
+// Literals the parser accepts come out exactly as they went in, except that
+// numbers are written in decimal without `_` separators. `[,]` is the empty
+// set: any token after `[` other than `]` makes a set.
+pub(crate) fn write_value(out: &mut String, value: &Value) {
+    match value {
+        Value::Int(i) => {
+            let _ = write!(out, "{}", i);
+        }
+        #[cfg(feature = "decimal")]
+        Value::Decimal(d) => {
+            let _ = write!(out, "{}m", d);
+        }
+        Value::Float(f) => {
+            let _ = write!(out, "{:?}", f.0);
+        }
+        Value::Bool(b) => {
+            let _ = write!(out, "{}", b);
+        }
+        Value::Char(c) => {
+            let _ = write!(out, "'{}'", c);
+        }
+        Value::Str(s) => write_string(out, s),
+        Value::Atom(a) => {
+            let _ = write!(out, ":{}", a.name());
+        }
+        Value::List(items) => write_items(out, items.iter()),
+        // Not literals; these are the calls that make them.
+        Value::IntArray(items) => {
+            let _ = write!(out, "intarray({:?})", items);
+        }
+        Value::FloatArray(items) => {
+            let floats: Vec<f64> = items.iter().map(|f| f.0).collect();
+            let _ = write!(out, "floatarray({:?})", floats);
+        }
+        Value::Range(r) if r.step() == 1 => {
+            let _ = write!(out, "range({}, {})", r.start(), r.end());
+        }
+        Value::Range(r) => {
+            let _ = write!(out, "range({}, {}, {})", r.start(), r.end(), r.step());
+        }
+        Value::Set(items) if items.is_empty() => out.push_str("[,]"),
+        // Sorted, so a set prints the same way on every run.
+        Value::Set(items) => {
+            let mut items: Vec<&Value> = items.iter().collect();
+            items.sort();
+            write_items(out, items.into_iter())
+        }
+        // Maps and nil only come from builtins such as `jsonparse`, never
+        // from a literal, so these are for display.
+        Value::Map(entries) => {
+            out.push('{');
+            for (i, (key, item)) in entries.iter().enumerate() {
+                if i > 0 {
+                    out.push_str(", ");
+                }
+                let _ = write!(out, "\"{}\": ", key);
+                write_value(out, item);
+            }
+            out.push('}');
+        }
+        Value::Extern(e) => {
+            let _ = write!(out, "<{}>", e.type_name());
+        }
+        Value::Nil => out.push_str("nil"),
+    }
+}
+
+fn write_items<'a>(out: &mut String, items: impl Iterator<Item = &'a Value>) {
+    out.push('[');
+    for (i, item) in items.enumerate() {
+        if i > 0 {
+            out.push_str(", ");
+        }
+        write_value(out, item);
+    }
+    out.push(']');
+}
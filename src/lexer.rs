@@ -0,0 +1,441 @@
+// ===== Lexer =====
+// Names and literal text borrow from the source, so lexing allocates nothing
+// per token.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Token<'a> {
+    Let,
+    Branch,
+    Merge,
+    Print,
+    Input,
+    Exit,
+    Assert,
+    Identifier(&'a str),
+    Number(i64),
+    Float(f64),
+    // `12.50m`, kept as written: `Value::Decimal` is behind a feature, so
+    // the parser decides what to make of it.
+    Decimal(&'a str),
+    Bool(bool),
+    // `'a'`: exactly one Unicode scalar value. There are no escapes, so the
+    // quote itself is `'''`.
+    Char(char),
+    // `:idle`, without the colon.
+    Atom(&'a str),
+    // Without the quotes.
+    Str(&'a str),
+    Equals,
+    LBrace,
+    RBrace,
+    Semicolon,
+    LBracket,
+    RBracket,
+    Comma,
+    LParen,
+    RParen,
+}
+
+// Byte range into the source text.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, serde::Serialize)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    pub fn new(start: usize, end: usize) -> Self {
+        Self { start, end }
+    }
+    pub fn shifted(self, delta: isize) -> Self {
+        Self::new(
+            self.start.saturating_add_signed(delta),
+            self.end.saturating_add_signed(delta),
+        )
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct LexError {
+    pub message: String,
+    pub offset: usize,
+}
+
+impl std::fmt::Display for LexError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} at byte {}", self.message, self.offset)
+    }
+}
+
+impl std::error::Error for LexError {}
+
+impl LexError {
+    // A short suggestion for the common mistakes.
+    pub fn hint(&self) -> Option<&'static str> {
+        if self.message.contains("'/'") {
+            Some("line comments start with `//`")
+        } else if self.message.contains("'.'") {
+            Some("write a float with a leading digit, e.g. `0.5`")
+        } else {
+            None
+        }
+    }
+}
+
+// Identifiers follow Unicode's XID rules, as Rust's do: a letter (in any
+// script) and then letters, digits, marks and `_`.
+fn is_name_start(c: char) -> bool {
+    unicode_ident::is_xid_start(c)
+}
+
+fn is_name_continue(c: char) -> bool {
+    unicode_ident::is_xid_continue(c)
+}
+
+// Whether `text` is a whole identifier, or the name in an atom literal.
+pub(crate) fn is_name(text: &str) -> bool {
+    text.starts_with(is_name_start) && text.chars().all(is_name_continue)
+}
+
+// `"""` opens a raw string, which runs to the next `"""` and holds newlines
+// and quotes as they are. Quotes right before the closing three belong to
+// the string, so `""""quoted""""` holds `"quoted"`.
+pub(crate) const RAW_QUOTES: &str = "\"\"\"";
+
+// Length of a raw string's text in `rest`, which follows its opening quotes;
+// `None` when nothing closes it.
+pub(crate) fn raw_string_len(rest: &str) -> Option<usize> {
+    let close = rest.find(RAW_QUOTES)?;
+    let extra = rest[close + 3..].bytes().take_while(|&b| b == b'"').count();
+    Some(close + extra)
+}
+
+// Length of the `.5`, `e9`, `.5e-3`, ... that makes the digits before it a
+// float literal. A `.` or `e` with no digit after it is not part of one.
+fn float_suffix(rest: &str) -> Option<usize> {
+    let digits = |s: &str| {
+        let n = s
+            .bytes()
+            .take_while(|b| b.is_ascii_digit() || *b == b'_')
+            .count();
+        s.starts_with(|c: char| c.is_ascii_digit()).then_some(n)
+    };
+    let fraction = match rest.strip_prefix('.') {
+        Some(after) => digits(after).map_or(0, |n| n + 1),
+        None => 0,
+    };
+    let exponent = rest[fraction..]
+        .strip_prefix(['e', 'E'])
+        .and_then(|after| {
+            let sign = usize::from(after.starts_with(['+', '-']));
+            Some(1 + sign + digits(&after[sign..])?)
+        })
+        .unwrap_or(0);
+    (fraction + exponent > 0).then_some(fraction + exponent)
+}
+
+// Length of the `.50m` or `m` that makes the digits before it a decimal
+// literal; the `m` must end the word.
+fn decimal_suffix(rest: &str) -> Option<usize> {
+    let fraction = match rest.strip_prefix('.') {
+        Some(digits) => {
+            let n = digits.bytes().take_while(u8::is_ascii_digit).count();
+            if n == 0 {
+                return None;
+            }
+            n + 1
+        }
+        None => 0,
+    };
+    let after = rest[fraction..].strip_prefix('m')?;
+    match after.chars().next() {
+        Some(c) if is_name_continue(c) => None,
+        _ => Some(fraction + 1),
+    }
+}
+
+// Produces tokens on demand. Errors are yielded in-line and lexing resumes
+// after the offending input, so callers may skip them or stop at the first.
+pub struct Lexer<'a> {
+    src: &'a str,
+    iter: std::iter::Peekable<std::str::CharIndices<'a>>,
+    // A `//` comment was cut off by the end of input rather than a newline.
+    comment_at_end: bool,
+    // Likewise for a string literal; more input might close it.
+    pub(crate) string_at_end: bool,
+}
+
+impl<'a> Lexer<'a> {
+    // A leading `#!` line is skipped so scripts can be made executable.
+    pub fn new(input: &'a str) -> Self {
+        let mut lexer = Self::without_shebang(input);
+        if input.starts_with("#!") {
+            while lexer.iter.next_if(|&(_, c)| c != '\n').is_some() {}
+        }
+        lexer
+    }
+
+    // For text that does not start a file, where `#!` is not special.
+    pub(crate) fn without_shebang(input: &'a str) -> Self {
+        Self {
+            src: input,
+            iter: input.char_indices().peekable(),
+            comment_at_end: false,
+            string_at_end: false,
+        }
+    }
+
+    fn offset(&mut self) -> usize {
+        self.iter.peek().map_or(self.src.len(), |&(i, _)| i)
+    }
+
+    fn single(&mut self, token: Token<'a>) -> Option<Result<Token<'a>, LexError>> {
+        self.iter.next();
+        Some(Ok(token))
+    }
+
+    // Skips whitespace and `//` line comments.
+    fn skip_trivia(&mut self) {
+        while let Some(&(i, c)) = self.iter.peek() {
+            if c.is_whitespace() {
+                self.iter.next();
+            } else if self.src[i..].starts_with("//") {
+                while self.iter.next_if(|&(_, c)| c != '\n').is_some() {}
+                self.comment_at_end = self.iter.peek().is_none();
+            } else {
+                break;
+            }
+        }
+    }
+
+    // Whether the input ran out inside a string literal, so that a caller
+    // reading line by line can wait for the rest of it.
+    pub fn string_at_end(&self) -> bool {
+        self.string_at_end
+    }
+
+    // Same as `next`, paired with the token's byte span.
+    pub fn next_spanned(&mut self) -> Option<Result<(Token<'a>, Span), LexError>> {
+        self.skip_trivia();
+        let start = self.offset();
+        let token = self.next_token()?;
+        let end = self.offset();
+        Some(token.map(|t| (t, Span::new(start, end))))
+    }
+
+    fn next_token(&mut self) -> Option<Result<Token<'a>, LexError>> {
+        let &(start, c) = self.iter.peek()?;
+        match c {
+            '=' => self.single(Token::Equals),
+            '{' => self.single(Token::LBrace),
+            '}' => self.single(Token::RBrace),
+            '[' => self.single(Token::LBracket),
+            ']' => self.single(Token::RBracket),
+            ',' => self.single(Token::Comma),
+            '(' => self.single(Token::LParen),
+            ')' => self.single(Token::RParen),
+            ';' => self.single(Token::Semicolon),
+            ':' => {
+                self.iter.next();
+                while self.iter.next_if(|&(_, c)| is_name_continue(c)).is_some() {}
+                let name = &self.src[start + 1..self.offset()];
+                if !name.starts_with(is_name_start) {
+                    return Some(Err(LexError {
+                        message: "expected a name after `:`".to_string(),
+                        offset: start,
+                    }));
+                }
+                Some(Ok(Token::Atom(name)))
+            }
+            '"' if self.src[start..].starts_with(RAW_QUOTES) => {
+                let rest = &self.src[start + 3..];
+                let Some(len) = raw_string_len(rest) else {
+                    while self.iter.next().is_some() {}
+                    self.string_at_end = true;
+                    return Some(Err(LexError {
+                        message: "unterminated raw string literal".to_string(),
+                        offset: start,
+                    }));
+                };
+                let end = start + 3 + len + 3;
+                while self.iter.next_if(|&(i, _)| i < end).is_some() {}
+                Some(Ok(Token::Str(&rest[..len])))
+            }
+            '"' => {
+                self.iter.next();
+                for (i, ch) in self.iter.by_ref() {
+                    if ch == '"' {
+                        return Some(Ok(Token::Str(&self.src[start + 1..i])));
+                    }
+                }
+                self.string_at_end = true;
+                Some(Err(LexError {
+                    message: "unterminated string literal".to_string(),
+                    offset: start,
+                }))
+            }
+            '\'' => {
+                self.iter.next();
+                let error = |message: &str| {
+                    Some(Err(LexError {
+                        message: message.to_string(),
+                        offset: start,
+                    }))
+                };
+                let c = match self.iter.next() {
+                    Some((_, '\'')) if self.iter.peek().map(|&(_, c)| c) != Some('\'') => {
+                        return error("empty character literal");
+                    }
+                    Some((_, '\n')) | None => return error("unterminated character literal"),
+                    Some((_, c)) => c,
+                };
+                match self.iter.next_if(|&(_, c)| c == '\'') {
+                    Some(_) => Some(Ok(Token::Char(c))),
+                    None => error("a character literal holds exactly one character"),
+                }
+            }
+            // `255`, `0xff`, `0b1111_1111`: `_` may separate digits anywhere
+            // after the first one (or the `0x`/`0b`).
+            c if c.is_ascii_digit() => {
+                let prefix = self.src.get(start..start + 2).unwrap_or("");
+                let (radix, base) = match prefix {
+                    "0x" | "0X" => (16, "hex"),
+                    "0b" | "0B" => (2, "binary"),
+                    _ => (10, "decimal"),
+                };
+                if radix != 10 {
+                    self.iter.next();
+                    self.iter.next();
+                }
+                let mut num: Option<i64> = Some(0);
+                let mut digits = 0;
+                while let Some(&(_, d)) = self.iter.peek() {
+                    if d == '_' {
+                        self.iter.next();
+                        continue;
+                    }
+                    let Some(value) = d.to_digit(radix) else {
+                        break;
+                    };
+                    num = num
+                        .and_then(|n| n.checked_mul(radix as i64))
+                        .and_then(|n| n.checked_add(value as i64));
+                    digits += 1;
+                    self.iter.next();
+                }
+                if digits == 0 {
+                    return Some(Err(LexError {
+                        message: format!("expected {} digits after `{}`", base, prefix),
+                        offset: start,
+                    }));
+                }
+                if let Some(len) =
+                    decimal_suffix(&self.src[self.offset()..]).filter(|_| radix == 10)
+                {
+                    for _ in 0..len {
+                        self.iter.next();
+                    }
+                    let text = &self.src[start..self.offset() - 1];
+                    return Some(Ok(Token::Decimal(text)));
+                }
+                if let Some(len) = float_suffix(&self.src[self.offset()..]).filter(|_| radix == 10)
+                {
+                    for _ in 0..len {
+                        self.iter.next();
+                    }
+                    let text = self.src[start..self.offset()].replace('_', "");
+                    return Some(match text.parse::<f64>() {
+                        Ok(f) if f.is_finite() => Ok(Token::Float(f)),
+                        _ => Err(LexError {
+                            message: format!(
+                                "float literal too large (the maximum is {:e})",
+                                f64::MAX
+                            ),
+                            offset: start,
+                        }),
+                    });
+                }
+                // The whole literal is consumed either way, so lexing goes on
+                // after it.
+                Some(num.map(Token::Number).ok_or_else(|| LexError {
+                    message: format!("integer literal too large (the maximum is {})", i64::MAX),
+                    offset: start,
+                }))
+            }
+            c if is_name_start(c) => {
+                while self.iter.next_if(|&(_, d)| is_name_continue(d)).is_some() {}
+                let ident = &self.src[start..self.offset()];
+                let token = match ident {
+                    "let" => Token::Let,
+                    "branch" => Token::Branch,
+                    "merge" => Token::Merge,
+                    "print" => Token::Print,
+                    "input" => Token::Input,
+                    "exit" => Token::Exit,
+                    "assert" => Token::Assert,
+                    "true" => Token::Bool(true),
+                    "false" => Token::Bool(false),
+                    _ => Token::Identifier(ident),
+                };
+                Some(Ok(token))
+            }
+            _ => {
+                self.iter.next();
+                Some(Err(LexError {
+                    message: format!("unexpected character {:?}", c),
+                    offset: start,
+                }))
+            }
+        }
+    }
+}
+
+impl<'a> Iterator for Lexer<'a> {
+    type Item = Result<Token<'a>, LexError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_spanned().map(|r| r.map(|(token, _)| token))
+    }
+}
+
+// Lenient front end: lexing errors are skipped, as the VM has always done.
+pub fn lex(input: &str) -> Vec<Token<'_>> {
+    Lexer::new(input).filter_map(Result::ok).collect()
+}
+
+// The tokens of `src` with spans offset by `base`.
+pub(crate) struct Lexed<'a> {
+    pub tokens: Vec<Token<'a>>,
+    pub spans: Vec<Span>,
+    // Offsets are shifted by `base` too.
+    pub errors: Vec<LexError>,
+    // No errors, and the text did not end inside a comment: in a slice of a
+    // larger buffer the comment may really go on past `src`.
+    pub clean: bool,
+}
+
+pub(crate) fn lex_spanned(src: &str, base: usize) -> Lexed<'_> {
+    let mut lexer = match base {
+        0 => Lexer::new(src),
+        _ => Lexer::without_shebang(src),
+    };
+    let (mut tokens, mut spans, mut errors) = (Vec::new(), Vec::new(), Vec::new());
+    while let Some(result) = lexer.next_spanned() {
+        match result {
+            Ok((token, span)) => {
+                tokens.push(token);
+                spans.push(Span::new(span.start + base, span.end + base));
+            }
+            Err(e) => errors.push(LexError {
+                offset: e.offset + base,
+                ..e
+            }),
+        }
+    }
+    let clean = errors.is_empty() && !lexer.comment_at_end;
+    Lexed {
+        tokens,
+        spans,
+        errors,
+        clean,
+    }
+}
@@ -0,0 +1,57 @@
+pub mod analysis;
+pub mod ast;
+pub mod binary;
+pub mod builtins;
+#[cfg(feature = "cdylib")]
+pub mod capi;
+pub mod date;
+pub mod doc;
+pub mod formatter;
+pub mod lexer;
+pub mod parser;
+pub mod report;
+pub mod value;
+pub mod vm;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+
+pub use ast::{ASTNode, Argument, InputType, PrintTarget, Visitor, VisitorMut};
+pub use lexer::{LexError, Lexer, Span, Token, lex};
+pub use parser::{IncrementalParser, ParseError, TextEdit, parse};
+pub use report::Report;
+pub use value::{
+    Atom, Extern, ExternEq, ExternHash, Float, FloatEq, HashState, IntRange, Overflow, Shared,
+    Value, ValueList, ValueSet,
+};
+#[cfg(not(target_arch = "wasm32"))]
+pub use vm::StdIo;
+pub use vm::{
+    Branch, Error, GenerationInfo, Interpreter, InterpreterBuilder, Io, MemoryIo, NodeEvent,
+    NodeHook, Observer, Pause, Program, Resolver, Resume, RuntimeError, RuntimeErrorKind, Stepper,
+    World, WorldSnapshot,
+};
+
+// ===== Thread safety =====
+// Programs and worlds are shipped to worker threads by multi-threaded hosts;
+// keep these bounds from regressing when new `Value` variants are added.
+// The `rc` feature gives them up on purpose.
+#[cfg(not(feature = "rc"))]
+const _: () = {
+    const fn assert_send_sync<T: Send + Sync>() {}
+    const fn assert_send<T: Send>() {}
+    assert_send_sync::<Value>();
+    assert_send_sync::<World>();
+    assert_send_sync::<WorldSnapshot>();
+    assert_send_sync::<Branch>();
+    assert_send_sync::<Program>();
+    assert_send::<Interpreter>();
+};
+
+// ===== Size =====
+// A `Value` is a tag plus one word: anything bigger (host objects) sits
+// behind a pointer. Decimals are 16 bytes inline, which the `decimal` feature
+// accepts to keep their arithmetic free of allocation.
+#[cfg(not(feature = "decimal"))]
+const _: () = assert!(std::mem::size_of::<Value>() <= 16);
+#[cfg(feature = "decimal")]
+const _: () = assert!(std::mem::size_of::<Value>() <= 24);
@@ -0,0 +1,976 @@
+use crate::ast::{ASTNode, Argument, InputType, Name, PrintTarget};
+use crate::lexer::{LexError, Lexer, Span, Token, lex_spanned};
+use crate::value::{Atom, Shared, Value};
+use crate::vm::Program;
+use std::collections::{HashMap, HashSet};
+
+// ===== Parser =====
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseError {
+    pub message: String,
+    pub span: Span,
+    // A short suggestion for fixing the error, shown by `Report`.
+    pub hint: Option<&'static str>,
+}
+
+impl std::fmt::Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} at {}..{}",
+            self.message, self.span.start, self.span.end
+        )
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+impl From<LexError> for ParseError {
+    fn from(e: LexError) -> Self {
+        ParseError {
+            hint: e.hint(),
+            span: Span::new(e.offset, e.offset + 1),
+            message: e.message,
+        }
+    }
+}
+
+impl ParseError {
+    fn with_hint(mut self, hint: &'static str) -> Self {
+        self.hint = Some(hint);
+        self
+    }
+}
+
+// Token iterator that also knows each token's span. Spans may be empty (for
+// `parse`), in which case nodes get `Span::default()` and line breaks are
+// unknown.
+struct Cursor<'a> {
+    tokens: &'a [Token<'a>],
+    spans: &'a [Span],
+    // The text the tokens were lexed from, which starts at byte `base`.
+    src: &'a str,
+    base: usize,
+    pos: usize,
+    // String literals seen so far, so repeats share one allocation.
+    strings: HashMap<&'a str, Shared<String>>,
+    // Likewise for variable and function names.
+    names: HashSet<Name>,
+    // A statement asked for a token after the last one.
+    past_end: bool,
+}
+
+impl<'a> Iterator for Cursor<'a> {
+    type Item = &'a Token<'a>;
+
+    fn next(&mut self) -> Option<&'a Token<'a>> {
+        let Some(token) = self.tokens.get(self.pos) else {
+            self.past_end = true;
+            return None;
+        };
+        self.pos += 1;
+        Some(token)
+    }
+}
+
+impl<'a> Cursor<'a> {
+    fn peek(&self) -> Option<&'a Token<'a>> {
+        self.tokens.get(self.pos)
+    }
+    // Consumes the next token if it is `token`.
+    fn eat(&mut self, token: &Token) -> bool {
+        self.past_end |= self.peek().is_none();
+        let found = self.peek() == Some(token);
+        if found {
+            self.pos += 1;
+        }
+        found
+    }
+    fn string(&mut self, text: &'a str) -> Value {
+        let shared = self
+            .strings
+            .entry(text)
+            .or_insert_with(|| Shared::new(text.to_string()));
+        Value::Str(shared.clone())
+    }
+    fn name(&mut self, text: &str) -> Name {
+        match self.names.get(text) {
+            Some(name) => name.clone(),
+            None => {
+                let name = Name::from(text);
+                self.names.insert(name.clone());
+                name
+            }
+        }
+    }
+    // Index of the most recently consumed token.
+    fn mark(&self) -> usize {
+        self.pos.saturating_sub(1)
+    }
+    fn span_at(&self, i: usize) -> Span {
+        self.spans.get(i).copied().unwrap_or_default()
+    }
+    // Span from token `start` through the most recently consumed token.
+    fn span_from(&self, start: usize) -> Span {
+        Span::new(self.span_at(start).start, self.span_at(self.mark()).end)
+    }
+    // Error pointing at the token just consumed.
+    fn error(&self, message: &str) -> ParseError {
+        ParseError {
+            message: message.to_string(),
+            span: self.span_at(self.mark()),
+            hint: None,
+        }
+    }
+    // Next token, or an error at end of input.
+    fn expect(&mut self, message: &str) -> Result<&'a Token<'a>, ParseError> {
+        self.next().ok_or_else(|| {
+            let end = self.span_at(self.tokens.len().saturating_sub(1)).end;
+            ParseError {
+                message: format!("{}, found end of input", message),
+                span: Span::new(end, end),
+                hint: None,
+            }
+        })
+    }
+    // Whether a line break comes between token `i` and the one before it;
+    // for `i` past the last token, whether one follows the last token.
+    fn line_break_before(&self, i: usize) -> bool {
+        if self.spans.is_empty() || i == 0 {
+            return false;
+        }
+        let start = self.spans[i - 1].end - self.base;
+        let end = self
+            .spans
+            .get(i)
+            .map_or(self.src.len(), |s| s.start - self.base);
+        self.src[start..end].contains('\n')
+    }
+    // `eat`, but only on the line of the token just consumed.
+    fn eat_on_line(&mut self, token: &Token) -> bool {
+        !self.line_break_before(self.pos) && self.eat(token)
+    }
+    // Ends a statement, which needs a `;` (`let` and calls take their own),
+    // a line break, a `}` or the end of input after it. A statement carries
+    // on past a line break only while it is incomplete (after `let x =`,
+    // inside a call's parentheses, ...), and optional parts such as an
+    // assert's `, "message"` must start on the line it would end on.
+    fn end_statement(&mut self) -> Result<(), ParseError> {
+        let ended = self.pos > 0 && self.tokens[self.pos - 1] == Token::Semicolon;
+        if ended
+            || self.line_break_before(self.pos)
+            || self.eat_on_line(&Token::Semicolon)
+            || self.spans.is_empty()
+        {
+            return Ok(());
+        }
+        match self.peek() {
+            None | Some(Token::RBrace) => Ok(()),
+            Some(_) => Err(ParseError {
+                message: "expected `;` or a line break after the statement".to_string(),
+                span: self.span_at(self.pos),
+                hint: Some("put each statement on its own line, or separate them with `;`"),
+            }),
+        }
+    }
+    // Skips the rest of a broken statement, which began at token `start`:
+    // past the next `;`, or up to the next line. A `}` ends it too; inside a
+    // branch body it is left for the body to close on. The token that
+    // triggered the error may itself be that boundary.
+    fn synchronize(&mut self, in_body: bool, start: usize) {
+        if self.pos > 0 {
+            match self.tokens[self.pos - 1] {
+                Token::Semicolon => return,
+                Token::RBrace if in_body => {
+                    self.pos -= 1;
+                    return;
+                }
+                Token::RBrace => return,
+                _ if self.pos - 1 > start && self.line_break_before(self.pos - 1) => {
+                    self.pos -= 1;
+                    return;
+                }
+                _ => {}
+            }
+        }
+        while let Some(token) = self.tokens.get(self.pos) {
+            if self.line_break_before(self.pos) {
+                return;
+            }
+            match token {
+                Token::Semicolon => {
+                    self.pos += 1;
+                    return;
+                }
+                Token::RBrace if in_body => return,
+                Token::RBrace => {
+                    self.pos += 1;
+                    return;
+                }
+                _ => self.pos += 1,
+            }
+        }
+    }
+}
+
+fn parse_let(tokens: &mut Cursor) -> Result<ASTNode, ParseError> {
+    let start = tokens.mark();
+    let Token::Identifier(name) = tokens.expect("expected a variable name after `let`")? else {
+        return Err(tokens.error("expected a variable name after `let`"));
+    };
+    let Token::Equals = tokens.expect("expected `=`")? else {
+        return Err(tokens
+            .error("expected `=`")
+            .with_hint("write `let <name> = <value>;`"));
+    };
+    let value = match tokens.expect("expected a value")? {
+        Token::Number(n) => Value::Int(*n),
+        Token::Float(f) => Value::from(*f),
+        Token::Decimal(text) => parse_decimal(tokens, text)?,
+        Token::Bool(b) => Value::Bool(*b),
+        Token::Char(c) => Value::Char(*c),
+        Token::Atom(name) => Value::Atom(Atom::new(name)),
+        Token::Str(s) => tokens.string(s),
+        Token::Identifier(function) if tokens.eat(&Token::LParen) => {
+            let target = tokens.name(name);
+            return parse_call(tokens, Some(target), function, start);
+        }
+        Token::LBracket => {
+            match tokens.next() {
+                Some(Token::RBracket) => Value::List(Shared::default()), // empty list
+                _ => {
+                    tokens.eat(&Token::RBracket); // `[,]`
+                    Value::Set(Shared::default())
+                }
+            }
+        }
+        _ => {
+            return Err(tokens.error("invalid let value").with_hint(
+                "a value is a number, a string, a character, an atom like `:idle`, `true`, `false`, `[]` or a call like `rand()`",
+            ));
+        }
+    };
+    tokens.eat_on_line(&Token::Semicolon);
+    Ok(ASTNode::Let {
+        name: tokens.name(name),
+        value,
+        span: tokens.span_from(start),
+    })
+}
+
+// `text` is the literal without its `m`.
+#[cfg(feature = "decimal")]
+fn parse_decimal(tokens: &Cursor, text: &str) -> Result<Value, ParseError> {
+    rust_decimal::Decimal::from_str_exact(text)
+        .map(Value::Decimal)
+        .map_err(|_| {
+            tokens
+                .error("decimal literal out of range")
+                .with_hint("decimals hold at most 28 digits")
+        })
+}
+
+#[cfg(not(feature = "decimal"))]
+fn parse_decimal(tokens: &Cursor, _text: &str) -> Result<Value, ParseError> {
+    Err(tokens
+        .error("decimal literals need the `decimal` feature")
+        .with_hint("rebuild sntvm with `--features decimal`"))
+}
+
+fn parse_argument<'a>(
+    tokens: &mut Cursor<'a>,
+    token: &'a Token<'a>,
+) -> Result<Argument, ParseError> {
+    Ok(match token {
+        Token::Identifier(name) => Argument::Variable(tokens.name(name)),
+        Token::Number(n) => Argument::Value(Value::Int(*n)),
+        Token::Float(f) => Argument::Value(Value::from(*f)),
+        Token::Decimal(text) => Argument::Value(parse_decimal(tokens, text)?),
+        Token::Bool(b) => Argument::Value(Value::Bool(*b)),
+        Token::Char(c) => Argument::Value(Value::Char(*c)),
+        Token::Atom(name) => Argument::Value(Value::Atom(Atom::new(name))),
+        Token::Str(s) => Argument::Value(tokens.string(s)),
+        Token::LBracket if tokens.eat(&Token::RBracket) => {
+            Argument::Value(Value::List(Shared::default()))
+        }
+        _ => {
+            return Err(tokens
+                .error("invalid argument")
+                .with_hint("an argument is a variable name or a literal value"));
+        }
+    })
+}
+
+// `function(arg, ...)` after its `(`; `start` is the statement's first token.
+fn parse_call(
+    tokens: &mut Cursor,
+    target: Option<Name>,
+    function: &str,
+    start: usize,
+) -> Result<ASTNode, ParseError> {
+    let mut args = Vec::new();
+    if !tokens.eat(&Token::RParen) {
+        loop {
+            let token = tokens.expect("expected an argument")?;
+            args.push(parse_argument(tokens, token)?);
+            match tokens.expect("expected `,` or `)`")? {
+                Token::Comma => {}
+                Token::RParen => break,
+                _ => {
+                    return Err(tokens
+                        .error("expected `,` or `)`")
+                        .with_hint("separate arguments with `,` and close the call with `)`"));
+                }
+            }
+        }
+    }
+    tokens.eat_on_line(&Token::Semicolon);
+    Ok(ASTNode::Call {
+        target,
+        function: tokens.name(function),
+        args,
+        span: tokens.span_from(start),
+    })
+}
+
+fn parse_print(tokens: &mut Cursor) -> Result<ASTNode, ParseError> {
+    let start = tokens.mark();
+    let target = match tokens.expect("expected a print target")? {
+        Token::Identifier(name) => PrintTarget::Variable(tokens.name(name)),
+        Token::Number(n) => PrintTarget::Value(Value::Int(*n)),
+        Token::Float(f) => PrintTarget::Value(Value::from(*f)),
+        Token::Decimal(text) => PrintTarget::Value(parse_decimal(tokens, text)?),
+        Token::Char(c) => PrintTarget::Value(Value::Char(*c)),
+        Token::Atom(name) => PrintTarget::Value(Value::Atom(Atom::new(name))),
+        Token::Str(s) => PrintTarget::Value(tokens.string(s)),
+        _ => {
+            return Err(tokens
+                .error("invalid print target")
+                .with_hint("`print` takes a variable name, a number or a string"));
+        }
+    };
+    Ok(ASTNode::Print {
+        target,
+        span: tokens.span_from(start),
+    })
+}
+
+fn parse_input(tokens: &mut Cursor) -> Result<ASTNode, ParseError> {
+    let start = tokens.mark();
+    let mut token = tokens.expect("expected a prompt string after `input`")?;
+    let mut input_type = InputType::Str;
+    if let Token::Identifier(name) = token {
+        input_type = match *name {
+            "int" => InputType::Int,
+            "float" => InputType::Float,
+            "str" => InputType::Str,
+            _ => {
+                return Err(tokens
+                    .error("expected a prompt string after `input`")
+                    .with_hint(
+                        "write `input \"<prompt>\" <name>;` or `input int \"<prompt>\" <name>;`",
+                    ));
+            }
+        };
+        token = tokens.expect("expected a prompt string")?;
+    }
+    let Token::Str(prompt) = token else {
+        return Err(tokens
+            .error("expected a prompt string")
+            .with_hint("write `input \"<prompt>\" <name>;`"));
+    };
+    let Token::Identifier(var) = tokens.expect("expected a variable name")? else {
+        return Err(tokens.error("expected a variable name"));
+    };
+    Ok(ASTNode::Input {
+        prompt: Some(prompt.to_string()),
+        input_type,
+        variable: tokens.name(var),
+        span: tokens.span_from(start),
+    })
+}
+
+fn parse_merge(tokens: &mut Cursor) -> Result<ASTNode, ParseError> {
+    let start = tokens.mark();
+    let Token::Identifier(name) = tokens.expect("expected a variable name after `merge`")? else {
+        return Err(tokens.error("expected a variable name after `merge`"));
+    };
+    Ok(ASTNode::Merge {
+        variable: tokens.name(name),
+        span: tokens.span_from(start),
+    })
+}
+
+fn parse_exit(tokens: &mut Cursor) -> Result<ASTNode, ParseError> {
+    let start = tokens.mark();
+    let Token::Number(code) = tokens.expect("expected an exit code after `exit`")? else {
+        return Err(tokens
+            .error("expected an exit code after `exit`")
+            .with_hint("write `exit <integer>;`"));
+    };
+    let Ok(code) = i32::try_from(*code) else {
+        return Err(tokens
+            .error("exit code out of range")
+            .with_hint("exit codes fit in 32 bits; most systems only keep 0-255"));
+    };
+    Ok(ASTNode::Exit {
+        code,
+        span: tokens.span_from(start),
+    })
+}
+
+fn parse_assert(tokens: &mut Cursor) -> Result<ASTNode, ParseError> {
+    let start = tokens.mark();
+    let token = tokens.expect("expected a condition after `assert`")?;
+    let condition = parse_argument(tokens, token).map_err(|_| {
+        tokens
+            .error("expected a condition after `assert`")
+            .with_hint("write `assert <name>;` or `assert <name>, \"<message>\";`")
+    })?;
+    let mut message = None;
+    if tokens.eat_on_line(&Token::Comma) {
+        let Token::Str(text) = tokens.expect("expected a message string")? else {
+            return Err(tokens.error("expected a message string"));
+        };
+        message = Some(text.to_string());
+    }
+    Ok(ASTNode::Assert {
+        condition,
+        message,
+        span: tokens.span_from(start),
+    })
+}
+
+// `listpush <var> <int>` / `setinsert <var> <int>`; returns (variable, value).
+fn parse_collection_op(tokens: &mut Cursor, op: &str) -> Result<(Name, Value), ParseError> {
+    let message = format!("expected a variable name after `{}`", op);
+    let Token::Identifier(var) = tokens.expect(&message)? else {
+        return Err(tokens.error(&message));
+    };
+    let Token::Number(n) = tokens.expect("expected an integer")? else {
+        return Err(tokens.error("expected an integer"));
+    };
+    Ok((tokens.name(var), Value::Int(*n)))
+}
+
+// `listpopfront <var> <target>` / `listpopback ...`; returns (variable, target).
+fn parse_list_pop(tokens: &mut Cursor, op: &str) -> Result<(Name, Name), ParseError> {
+    let message = format!("expected a variable name after `{}`", op);
+    let Token::Identifier(var) = tokens.expect(&message)? else {
+        return Err(tokens.error(&message));
+    };
+    let Token::Identifier(target) = tokens.expect("expected a variable to store the element in")?
+    else {
+        return Err(tokens
+            .error("expected a variable to store the element in")
+            .with_hint("name the list, then the variable that receives the element"));
+    };
+    Ok((tokens.name(var), tokens.name(target)))
+}
+
+fn parse_branch(tokens: &mut Cursor, errors: &mut Vec<ParseError>) -> Result<ASTNode, ParseError> {
+    let start = tokens.mark();
+    let variable = match tokens.expect("expected a variable name after `branch`")? {
+        Token::Identifier(name) => tokens.name(name),
+        _ => return Err(tokens.error("expected a variable name after `branch`")),
+    };
+    match tokens.expect("expected `{`")? {
+        Token::LBrace => {}
+        _ => return Err(tokens.error("expected `{`")),
+    }
+    let mut body = Vec::new();
+    loop {
+        let Some(token) = tokens.next() else {
+            errors.push(ParseError {
+                message: "unclosed branch body: expected `}`".to_string(),
+                span: tokens.span_from(start),
+                hint: Some("every `branch <name> {` needs a matching `}`"),
+            });
+            break;
+        };
+        let stmt_start = tokens.mark();
+        let node = match token {
+            Token::RBrace => break,
+            Token::Let => parse_let(tokens),
+            Token::Branch => parse_branch(tokens, errors),
+            Token::Merge => parse_merge(tokens),
+            Token::Print => parse_print(tokens),
+            Token::Input => parse_input(tokens),
+            Token::Exit => parse_exit(tokens),
+            Token::Assert => parse_assert(tokens),
+            Token::Identifier(function) if tokens.eat(&Token::LParen) => {
+                parse_call(tokens, None, function, stmt_start)
+            }
+            Token::Identifier(ident) if *ident == "listpush" => parse_collection_op(tokens, ident)
+                .map(|(variable, value)| ASTNode::ListPush {
+                    variable,
+                    value,
+                    span: tokens.span_from(stmt_start),
+                }),
+            Token::Identifier(ident) if *ident == "listpushfront" => {
+                parse_collection_op(tokens, ident).map(|(variable, value)| ASTNode::ListPushFront {
+                    variable,
+                    value,
+                    span: tokens.span_from(stmt_start),
+                })
+            }
+            Token::Identifier(ident) if *ident == "listpopfront" => parse_list_pop(tokens, ident)
+                .map(|(variable, target)| ASTNode::ListPopFront {
+                    variable,
+                    target,
+                    span: tokens.span_from(stmt_start),
+                }),
+            Token::Identifier(ident) if *ident == "listpopback" => parse_list_pop(tokens, ident)
+                .map(|(variable, target)| ASTNode::ListPopBack {
+                    variable,
+                    target,
+                    span: tokens.span_from(stmt_start),
+                }),
+            Token::Identifier(ident) if *ident == "setinsert" => parse_collection_op(tokens, ident)
+                .map(|(variable, value)| ASTNode::SetInsert {
+                    variable,
+                    value,
+                    span: tokens.span_from(stmt_start),
+                }),
+            Token::Semicolon => continue,
+            _ => Err(unexpected(tokens)),
+        };
+        match node {
+            Ok(node) => {
+                body.push(node);
+                if let Err(e) = tokens.end_statement() {
+                    errors.push(e);
+                }
+            }
+            Err(e) => {
+                errors.push(e);
+                tokens.synchronize(true, stmt_start);
+            }
+        }
+    }
+    Ok(ASTNode::Branch {
+        variable,
+        body,
+        span: tokens.span_from(start),
+    })
+}
+
+// Parses the top-level statement introduced by `token`. An empty statement
+// (a stray `;`) yields `None`.
+fn parse_statement(
+    token: &Token,
+    tokens: &mut Cursor,
+    errors: &mut Vec<ParseError>,
+) -> Option<Result<ASTNode, ParseError>> {
+    match token {
+        Token::Let => Some(parse_let(tokens)),
+        Token::Branch => Some(parse_branch(tokens, errors)),
+        Token::Merge => Some(parse_merge(tokens)),
+        Token::Print => Some(parse_print(tokens)),
+        Token::Input => Some(parse_input(tokens)),
+        Token::Exit => Some(parse_exit(tokens)),
+        Token::Assert => Some(parse_assert(tokens)),
+        Token::Identifier(function) if tokens.eat(&Token::LParen) => {
+            let start = tokens.mark() - 1;
+            Some(parse_call(tokens, None, function, start))
+        }
+        Token::RBrace => Some(Err(tokens
+            .error("unmatched `}`")
+            .with_hint("this `}` does not close any `branch`"))),
+        Token::Semicolon => None,
+        _ => Some(Err(unexpected(tokens))),
+    }
+}
+
+// Error for a token that cannot start a statement, just consumed.
+fn unexpected(tokens: &Cursor) -> ParseError {
+    tokens.error("unexpected token").with_hint(
+        "a statement starts with a keyword such as `let` or `print`, or is a call like `f(x)`",
+    )
+}
+
+// Strict front end: the AST if there were no syntax errors, otherwise every
+// error that was found.
+pub fn parse(tokens: &[Token<'_>]) -> Result<Vec<ASTNode>, Vec<ParseError>> {
+    let (ast, errors) = parse_spanned(tokens, &[], "");
+    if errors.is_empty() {
+        Ok(ast)
+    } else {
+        Err(errors)
+    }
+}
+
+// Error-recovering parse that records source spans on the nodes. `spans[i]`
+// is the span of `tokens[i]` in `src`, whose line breaks end statements.
+// Broken statements are reported and skipped, so the AST holds everything
+// that did parse.
+pub fn parse_spanned(
+    tokens: &[Token<'_>],
+    spans: &[Span],
+    src: &str,
+) -> (Vec<ASTNode>, Vec<ParseError>) {
+    let (ast, errors, _) = parse_tokens(tokens, spans, src, 0);
+    (ast, errors)
+}
+
+// `parse_spanned` for tokens lexed from `src` at byte `base`, and whether any
+// statement ran into the end of the tokens.
+fn parse_tokens(
+    tokens: &[Token<'_>],
+    spans: &[Span],
+    src: &str,
+    base: usize,
+) -> (Vec<ASTNode>, Vec<ParseError>, bool) {
+    let mut cursor = Cursor {
+        tokens,
+        spans,
+        src,
+        base,
+        pos: 0,
+        strings: HashMap::new(),
+        names: HashSet::new(),
+        past_end: false,
+    };
+    let mut ast = Vec::new();
+    let mut errors = Vec::new();
+    while let Some(token) = tokens.get(cursor.pos) {
+        let start = cursor.pos;
+        cursor.pos += 1;
+        match parse_statement(token, &mut cursor, &mut errors) {
+            Some(Ok(node)) => {
+                ast.push(node);
+                if let Err(e) = cursor.end_statement() {
+                    errors.push(e);
+                }
+            }
+            Some(Err(e)) => {
+                errors.push(e);
+                if token != &Token::RBrace {
+                    cursor.synchronize(false, start);
+                }
+            }
+            None => {}
+        }
+    }
+    (ast, errors, cursor.past_end)
+}
+
+// What `Program::compile` parses: the tokens `lex` keeps, with spans.
+pub(crate) fn parse_source(src: &str) -> (Vec<ASTNode>, Vec<ParseError>) {
+    #[cfg(all(feature = "parallel", not(feature = "rc")))]
+    if src.len() >= PARALLEL_MIN
+        && let Some(ast) = parse_parallel(src)
+    {
+        return (ast, Vec::new());
+    }
+    let lexed = lex_spanned(src, 0);
+    let (ast, errors) = parse_spanned(&lexed.tokens, &lexed.spans, src);
+    (ast, with_lex_errors(errors, lexed.errors))
+}
+
+// Both kinds of error, ordered by position.
+fn with_lex_errors(mut errors: Vec<ParseError>, lex_errors: Vec<LexError>) -> Vec<ParseError> {
+    errors.extend(lex_errors.into_iter().map(ParseError::from));
+    errors.sort_by_key(|e| e.span.start);
+    errors
+}
+
+// For streaming: parses `src`, which starts at byte `base` of the input, if
+// it holds only whole statements. `None` means more input could still
+// complete it: a string or `{` is open, or a statement was cut off. Once the
+// input is `finished`, whatever is there is parsed.
+pub(crate) fn parse_statements(
+    src: &str,
+    base: usize,
+    finished: bool,
+) -> Option<(Vec<ASTNode>, Vec<ParseError>)> {
+    let mut lexer = match base {
+        0 => Lexer::new(src),
+        _ => Lexer::without_shebang(src),
+    };
+    let (mut tokens, mut spans, mut lex_errors) = (Vec::new(), Vec::new(), Vec::new());
+    while let Some(result) = lexer.next_spanned() {
+        match result {
+            Ok((token, span)) => {
+                tokens.push(token);
+                spans.push(Span::new(span.start + base, span.end + base));
+            }
+            Err(e) => lex_errors.push(LexError {
+                offset: e.offset + base,
+                ..e
+            }),
+        }
+    }
+    let depth: i64 = tokens
+        .iter()
+        .map(|token| match token {
+            Token::LBrace => 1,
+            Token::RBrace => -1,
+            _ => 0,
+        })
+        .sum();
+    if !finished && (lexer.string_at_end || depth > 0) {
+        return None;
+    }
+    let (ast, errors, past_end) = parse_tokens(&tokens, &spans, src, base);
+    (finished || !past_end).then(|| (ast, with_lex_errors(errors, lex_errors)))
+}
+
+// ===== Parallel parsing =====
+// Big sources are cut at top-level line ends, and the pieces
+// lexed and parsed on rayon's pool. A piece only counts if it lexed and
+// parsed cleanly without a statement running off its end; otherwise the
+// whole source is parsed again in one go, so errors and recovery come out
+// exactly as they would have.
+#[cfg(all(feature = "parallel", not(feature = "rc")))]
+const PARALLEL_MIN: usize = 1 << 20;
+
+#[cfg(all(feature = "parallel", not(feature = "rc")))]
+fn parse_parallel(src: &str) -> Option<Vec<ASTNode>> {
+    use rayon::prelude::*;
+    let target = (src.len() / (rayon::current_num_threads() * 4)).max(1 << 16);
+    let points = split_points(src, target);
+    let pieces: Vec<Option<Vec<ASTNode>>> = points
+        .par_windows(2)
+        .map(|bounds| {
+            let (start, end) = (bounds[0], bounds[1]);
+            let lexed = lex_spanned(&src[start..end], start);
+            let (ast, errors, past_end) =
+                parse_tokens(&lexed.tokens, &lexed.spans, &src[start..end], start);
+            let whole = !past_end || end == src.len();
+            (lexed.clean && errors.is_empty() && whole).then_some(ast)
+        })
+        .collect();
+    let mut ast = Vec::new();
+    for piece in pieces {
+        ast.extend(piece?);
+    }
+    Some(ast)
+}
+
+// Offsets just past a line end outside any string, character literal,
+// comment, `{}` block or `()` and not after a `=` or `,`, at least `target`
+// bytes apart, with 0 and the end of the source. A statement that still goes
+// on past the line runs off its piece, which sends the source back to a
+// single parse.
+#[cfg(all(feature = "parallel", not(feature = "rc")))]
+fn split_points(src: &str, target: usize) -> Vec<usize> {
+    let bytes = src.as_bytes();
+    let mut points = vec![0];
+    let mut depth = 0i64;
+    let mut i = if src.starts_with("#!") {
+        src.find('\n').unwrap_or(src.len())
+    } else {
+        0
+    };
+    while i < bytes.len() {
+        match bytes[i] {
+            b'"' if src[i..].starts_with(crate::lexer::RAW_QUOTES) => {
+                let len = crate::lexer::raw_string_len(&src[i + 3..]);
+                i = len.map_or(bytes.len(), |n| i + n + 5);
+            }
+            b'"' => i = src[i + 1..].find('"').map_or(bytes.len(), |j| i + 1 + j),
+            // `'x'`, where x may itself be a quote.
+            b'\'' => {
+                i += src[i + 1..].chars().next().map_or(0, char::len_utf8);
+                if bytes.get(i + 1) == Some(&b'\'') {
+                    i += 1;
+                }
+            }
+            b'/' if bytes.get(i + 1) == Some(&b'/') => {
+                i = src[i..].find('\n').map_or(bytes.len(), |j| i + j);
+            }
+            b'{' | b'(' => depth += 1,
+            b'}' | b')' => depth -= 1,
+            b'\n'
+                if depth == 0
+                    && i + 1 - points[points.len() - 1] >= target
+                    && !src[..i].trim_end().ends_with(['=', ',']) =>
+            {
+                points.push(i + 1);
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+    if points[points.len() - 1] < bytes.len() {
+        points.push(bytes.len());
+    }
+    points
+}
+
+// ===== Incremental parsing =====
+// Replace the bytes in `range` of the current source with `text`.
+#[derive(Debug, Clone)]
+pub struct TextEdit {
+    pub range: std::ops::Range<usize>,
+    pub text: String,
+}
+
+// Keeps a source buffer and its top-level AST in sync for editor tooling.
+// An edit re-lexes and re-parses only the statements it touches (plus any
+// stray tokens between them); all other nodes are kept and their spans shifted.
+#[derive(Debug, Clone)]
+pub struct IncrementalParser {
+    source: String,
+    ast: Vec<ASTNode>,
+    errors: Vec<ParseError>,
+    // An unclosed `{` swallows the rest of the file, so region re-parses are
+    // only valid while the whole buffer is balanced.
+    balanced: bool,
+}
+
+fn shift_spans(node: &mut ASTNode, delta: isize) {
+    let span = node.span_mut();
+    *span = span.shifted(delta);
+    if let ASTNode::Branch { body, .. } = node {
+        for child in body {
+            shift_spans(child, delta);
+        }
+    }
+}
+
+fn separated(src: &str, at: usize) -> bool {
+    src[..at].ends_with(char::is_whitespace) || src[at..].starts_with(char::is_whitespace)
+}
+
+fn statement_break(src: &str, at: usize) -> bool {
+    let rest = src[at..].trim_start_matches(|c: char| c.is_whitespace() && c != '\n');
+    src[..at].ends_with(';') || rest.starts_with(['\n', ';']) || rest.starts_with("//")
+}
+
+fn balanced(tokens: &[Token<'_>]) -> bool {
+    let mut depth = 0i32;
+    for token in tokens {
+        match token {
+            Token::LBrace | Token::LBracket | Token::LParen => depth += 1,
+            Token::RBrace | Token::RBracket | Token::RParen => depth -= 1,
+            _ => {}
+        }
+        if depth < 0 {
+            return false;
+        }
+    }
+    depth == 0
+}
+
+impl IncrementalParser {
+    pub fn new(source: impl Into<String>) -> Self {
+        let mut parser = Self {
+            source: source.into(),
+            ast: Vec::new(),
+            errors: Vec::new(),
+            balanced: false,
+        };
+        parser.reparse_all();
+        parser
+    }
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+    pub fn ast(&self) -> &[ASTNode] {
+        &self.ast
+    }
+    // Syntax errors in the current source, ordered by position.
+    pub fn errors(&self) -> &[ParseError] {
+        &self.errors
+    }
+    pub fn program(&self) -> Program {
+        Program::from_ast(self.ast.clone())
+    }
+
+    fn reparse_all(&mut self) {
+        let lexed = lex_spanned(&self.source, 0);
+        self.balanced = balanced(&lexed.tokens);
+        let (ast, errors) = parse_spanned(&lexed.tokens, &lexed.spans, &self.source);
+        (self.ast, self.errors) = (ast, with_lex_errors(errors, lexed.errors));
+    }
+
+    // Panics if the range is out of bounds or not on char boundaries.
+    pub fn apply(&mut self, edit: &TextEdit) {
+        let old_len = self.source.len();
+        let range = edit.range.clone();
+        self.source.replace_range(range.clone(), &edit.text);
+        let delta = edit.text.len() as isize - range.len() as isize;
+        if !self.balanced {
+            self.reparse_all();
+            return;
+        }
+
+        // Statements touching the edit, inclusively: text inserted right
+        // next to a token can extend that token.
+        let mut first = self.ast.partition_point(|n| n.span().end < range.start);
+        let mut last = self.ast.partition_point(|n| n.span().start <= range.end);
+        // Statements look one token ahead (for a `;` or a statement on the
+        // same line), so the one before the edit may change too. Also re-parse the next
+        // untouched statement and check below that it comes out unchanged:
+        // that proves the parser resynchronised.
+        first = first.saturating_sub(1);
+        if last < self.ast.len() {
+            last += 1;
+        }
+        // Grow the region until both ends sit on whitespace, so no token of
+        // the re-lexed region can fuse with a neighbouring one. The start
+        // must also end a line or follow a `;`, or the region would miss a
+        // statement that shares a line with the one before it.
+        let region_start = loop {
+            let start = if first > 0 {
+                self.ast[first - 1].span().end
+            } else {
+                0
+            };
+            if first == 0 || separated(&self.source, start) && statement_break(&self.source, start)
+            {
+                break start;
+            }
+            first -= 1;
+        };
+        let region_end = loop {
+            let end = self.ast.get(last).map_or(old_len, |n| n.span().start);
+            let end = end.saturating_add_signed(delta);
+            if last == self.ast.len() || separated(&self.source, end) {
+                break end;
+            }
+            last += 1;
+        };
+
+        let region = &self.source[region_start..region_end];
+        let lexed = lex_spanned(region, region_start);
+        let (tokens, spans) = (lexed.tokens, lexed.spans);
+        if !lexed.clean || !balanced(&tokens) {
+            // The edit may reach past the region (an open brace or string).
+            self.reparse_all();
+            return;
+        }
+        let (ast, mut errors, past_end) = parse_tokens(&tokens, &spans, region, region_start);
+        if last < self.ast.len() {
+            // A broken statement at the end may have been cut short: the
+            // whole parse would have read on into the next line.
+            let sync = self.ast[last - 1].span().shifted(delta);
+            if past_end || ast.last().map(ASTNode::span) != Some(sync) {
+                self.reparse_all();
+                return;
+            }
+        }
+        // Errors at end of input sit exactly on the region end when the
+        // region runs to the end of the buffer.
+        let old_region_end = if last == self.ast.len() {
+            usize::MAX
+        } else {
+            region_end.saturating_add_signed(-delta)
+        };
+        for node in &mut self.ast[last..] {
+            shift_spans(node, delta);
+        }
+        self.ast.splice(first..last, ast);
+        self.errors
+            .retain(|e| e.span.start < region_start || e.span.start >= old_region_end);
+        for e in &mut self.errors {
+            if e.span.start >= old_region_end {
+                e.span = e.span.shifted(delta);
+            }
+        }
+        self.errors.append(&mut errors);
+        self.errors.sort_by_key(|e| e.span.start);
+    }
+}
@@ -0,0 +1,137 @@
+use crate::analysis::Diagnostic;
+use crate::lexer::{LexError, Span};
+use crate::parser::ParseError;
+use crate::vm::{RuntimeError, RuntimeErrorKind};
+use std::fmt::Write;
+
+// ===== Diagnostic rendering =====
+// Turns any of the crate's errors into a compiler-style report:
+//
+//   error: invalid let value
+//    --> script.snt:1:9
+//     |
+//   1 | let x = ;
+//     |         ^
+//     = hint: a value is an integer, a string, `true`, `false` or `[]`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Report {
+    pub severity: &'static str,
+    pub message: String,
+    pub span: Option<Span>,
+    pub hint: Option<String>,
+}
+
+impl Report {
+    pub fn error(message: impl Into<String>, span: Option<Span>) -> Self {
+        Self {
+            severity: "error",
+            message: message.into(),
+            span,
+            hint: None,
+        }
+    }
+    pub fn with_hint(mut self, hint: impl Into<String>) -> Self {
+        self.hint = Some(hint.into());
+        self
+    }
+
+    // `path` is only used for display. Spans that don't fit `src` are
+    // reported without a snippet.
+    pub fn render(&self, path: &str, src: &str) -> String {
+        let mut out = format!("{}: {}\n", self.severity, self.message);
+        let location = self
+            .span
+            .filter(|span| span.start <= span.end && src.is_char_boundary(span.start))
+            .filter(|span| span.end <= src.len());
+        let Some(span) = location else {
+            let _ = writeln!(out, " --> {}", path);
+            self.render_hint(&mut out, 1);
+            return out;
+        };
+        let line_start = src[..span.start].rfind('\n').map_or(0, |i| i + 1);
+        let line_end = src[span.start..]
+            .find('\n')
+            .map_or(src.len(), |i| span.start + i);
+        let line_no = src[..span.start].matches('\n').count() + 1;
+        let text = &src[line_start..line_end];
+        let col = src[line_start..span.start].chars().count();
+        // Multi-line spans are underlined to the end of their first line.
+        let width = src
+            .get(span.start..span.end.min(line_end))
+            .map_or(1, |s| s.chars().count())
+            .max(1);
+        let gutter = line_no.to_string().len();
+
+        let _ = writeln!(out, "{:gutter$}--> {}:{}:{}", "", path, line_no, col + 1);
+        let _ = writeln!(out, "{:gutter$} |", "");
+        let _ = writeln!(out, "{} | {}", line_no, text);
+        let _ = writeln!(
+            out,
+            "{:gutter$} | {}{}",
+            "",
+            " ".repeat(col),
+            "^".repeat(width)
+        );
+        self.render_hint(&mut out, gutter);
+        out
+    }
+
+    fn render_hint(&self, out: &mut String, gutter: usize) {
+        if let Some(hint) = &self.hint {
+            let _ = writeln!(out, "{:gutter$} = hint: {}", "", hint);
+        }
+    }
+}
+
+impl From<&ParseError> for Report {
+    fn from(e: &ParseError) -> Self {
+        Report {
+            hint: e.hint.map(str::to_string),
+            ..Report::error(e.message.clone(), Some(e.span))
+        }
+    }
+}
+
+impl From<&LexError> for Report {
+    fn from(e: &LexError) -> Self {
+        Report {
+            hint: e.hint().map(str::to_string),
+            ..Report::error(e.message.clone(), Some(Span::new(e.offset, e.offset + 1)))
+        }
+    }
+}
+
+impl From<&RuntimeError> for Report {
+    fn from(e: &RuntimeError) -> Self {
+        let report = Report::error(e.message.clone(), e.span);
+        match e.kind {
+            RuntimeErrorKind::UndefinedVariable => report.with_hint("define it with `let` first"),
+            RuntimeErrorKind::MergeConflict => report.with_hint(
+                "the variable was merged after this branch was opened; the branch is stale",
+            ),
+            RuntimeErrorKind::TypeError => {
+                report.with_hint("`listpush` needs a list and `setinsert` a set")
+            }
+            RuntimeErrorKind::UnknownFunction => report.with_hint(format!(
+                "the builtins are {}",
+                crate::builtins::NAMES.join(", ")
+            )),
+            RuntimeErrorKind::Sandboxed => report.with_hint(
+                "the host disabled environment, file, network and process access for this run",
+            ),
+            RuntimeErrorKind::InvalidInput => {
+                report.with_hint("plain `input` accepts any text and stores it as a string")
+            }
+            RuntimeErrorKind::InputExhausted => {
+                report.with_hint("every `input` statement that runs needs a line of input")
+            }
+            _ => report,
+        }
+    }
+}
+
+impl From<&Diagnostic> for Report {
+    fn from(d: &Diagnostic) -> Self {
+        Report::error(d.message.clone(), Some(d.span))
+    }
+}
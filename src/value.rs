@@ -0,0 +1,1170 @@
+use indexmap::IndexMap;
+use serde::{Serialize, Serializer};
+use smallvec::SmallVec;
+use std::{
+    any::Any,
+    cmp::Ordering,
+    collections::{BTreeMap, HashMap, HashSet},
+    fmt,
+    hash::{Hash, Hasher},
+    sync::Arc,
+};
+
+// ===== Shared storage =====
+// Strings and collections are reference counted with `Arc` by default. The
+// `rc` feature swaps in `Rc` for single-threaded hosts, which saves the
+// atomic operations on every clone and drop but makes `Value` (and so
+// `Program`, `World` and `Interpreter`) neither `Send` nor `Sync`.
+#[cfg(not(feature = "rc"))]
+pub type Shared<T> = Arc<T>;
+#[cfg(feature = "rc")]
+pub type Shared<T> = std::rc::Rc<T>;
+
+// ===== Hashing =====
+// `World` and `Value::Set` hash short names and small values on nearly every
+// statement. With the default `fast-hash` feature they use foldhash, which
+// is several times faster than the standard SipHash but makes no promise
+// against keys crafted to collide; hosts that feed untrusted data into sets
+// can turn the feature off.
+#[cfg(feature = "fast-hash")]
+pub type HashState = foldhash::fast::RandomState;
+#[cfg(not(feature = "fast-hash"))]
+pub type HashState = std::hash::RandomState;
+
+// ===== Small collections =====
+// Most lists and sets in scripts hold a handful of elements. Up to `INLINE`
+// of them sit in the collection's own shared allocation rather than in a
+// second buffer, and a set builds no hash table until it outgrows that:
+// finding a member among eight by comparing them in turn is as quick as
+// hashing it.
+//
+// Past that, with the default `persistent` feature, a list becomes an RRB
+// vector and a set a hash trie. Both share structure between copies, so
+// changing a list that a branch snapshot also holds copies O(log n) nodes
+// instead of every element, and a list grows or shrinks at either end in
+// O(log n). Without the feature they spill into an ordinary buffer and
+// `HashSet`.
+pub const INLINE: usize = 8;
+
+#[cfg(all(feature = "persistent", not(feature = "rc")))]
+type PointerKind = imbl::shared_ptr::ArcK;
+#[cfg(all(feature = "persistent", feature = "rc"))]
+type PointerKind = imbl::shared_ptr::RcK;
+
+#[cfg(feature = "persistent")]
+type BigList = imbl::GenericVector<Value, PointerKind>;
+#[cfg(feature = "persistent")]
+type BigSet = imbl::GenericHashSet<Value, HashState, PointerKind>;
+#[cfg(not(feature = "persistent"))]
+type BigSet = HashSet<Value, HashState>;
+
+#[derive(Clone, Default)]
+pub struct ValueList(ListRepr);
+
+#[derive(Clone)]
+enum ListRepr {
+    Inline(SmallVec<[Value; INLINE]>),
+    #[cfg(feature = "persistent")]
+    Tree(BigList),
+}
+
+impl Default for ListRepr {
+    fn default() -> Self {
+        ListRepr::Inline(SmallVec::new())
+    }
+}
+
+impl ValueList {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    pub fn with_capacity(n: usize) -> Self {
+        #[cfg(feature = "persistent")]
+        if n > INLINE {
+            return ValueList(ListRepr::Tree(BigList::new()));
+        }
+        ValueList(ListRepr::Inline(SmallVec::with_capacity(n)))
+    }
+    pub fn len(&self) -> usize {
+        match &self.0 {
+            ListRepr::Inline(items) => items.len(),
+            #[cfg(feature = "persistent")]
+            ListRepr::Tree(items) => items.len(),
+        }
+    }
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+    pub fn get(&self, i: usize) -> Option<&Value> {
+        match &self.0 {
+            ListRepr::Inline(items) => items.get(i),
+            #[cfg(feature = "persistent")]
+            ListRepr::Tree(items) => items.get(i),
+        }
+    }
+    // Moves an inline list that is about to outgrow `INLINE` into a tree.
+    fn grow(&mut self) {
+        #[cfg(feature = "persistent")]
+        if let ListRepr::Inline(items) = &mut self.0
+            && items.len() >= INLINE
+        {
+            self.0 = ListRepr::Tree(items.drain(..).collect());
+        }
+    }
+    pub fn push(&mut self, value: Value) {
+        self.grow();
+        match &mut self.0 {
+            ListRepr::Inline(items) => items.push(value),
+            #[cfg(feature = "persistent")]
+            ListRepr::Tree(items) => items.push_back(value),
+        }
+    }
+    pub fn push_front(&mut self, value: Value) {
+        self.grow();
+        match &mut self.0 {
+            ListRepr::Inline(items) => items.insert(0, value),
+            #[cfg(feature = "persistent")]
+            ListRepr::Tree(items) => items.push_front(value),
+        }
+    }
+    pub fn pop(&mut self) -> Option<Value> {
+        match &mut self.0 {
+            ListRepr::Inline(items) => items.pop(),
+            #[cfg(feature = "persistent")]
+            ListRepr::Tree(items) => items.pop_back(),
+        }
+    }
+    pub fn pop_front(&mut self) -> Option<Value> {
+        match &mut self.0 {
+            ListRepr::Inline(items) if items.is_empty() => None,
+            ListRepr::Inline(items) => Some(items.remove(0)),
+            #[cfg(feature = "persistent")]
+            ListRepr::Tree(items) => items.pop_front(),
+        }
+    }
+    pub fn iter(&self) -> ListIter<'_> {
+        match &self.0 {
+            ListRepr::Inline(items) => ListIter::Inline(items.iter()),
+            #[cfg(feature = "persistent")]
+            ListRepr::Tree(items) => ListIter::Tree(items.iter()),
+        }
+    }
+    pub fn to_vec(&self) -> Vec<Value> {
+        self.iter().cloned().collect()
+    }
+}
+
+pub enum ListIter<'a> {
+    Inline(std::slice::Iter<'a, Value>),
+    #[cfg(feature = "persistent")]
+    Tree(imbl::vector::Iter<'a, Value, PointerKind>),
+}
+
+impl<'a> Iterator for ListIter<'a> {
+    type Item = &'a Value;
+    fn next(&mut self) -> Option<&'a Value> {
+        match self {
+            ListIter::Inline(iter) => iter.next(),
+            #[cfg(feature = "persistent")]
+            ListIter::Tree(iter) => iter.next(),
+        }
+    }
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        match self {
+            ListIter::Inline(iter) => iter.size_hint(),
+            #[cfg(feature = "persistent")]
+            ListIter::Tree(iter) => iter.size_hint(),
+        }
+    }
+}
+
+impl DoubleEndedIterator for ListIter<'_> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        match self {
+            ListIter::Inline(iter) => iter.next_back(),
+            #[cfg(feature = "persistent")]
+            ListIter::Tree(iter) => iter.next_back(),
+        }
+    }
+}
+
+impl ExactSizeIterator for ListIter<'_> {}
+
+impl<'a> IntoIterator for &'a ValueList {
+    type Item = &'a Value;
+    type IntoIter = ListIter<'a>;
+    fn into_iter(self) -> ListIter<'a> {
+        self.iter()
+    }
+}
+
+impl PartialEq for ValueList {
+    fn eq(&self, other: &Self) -> bool {
+        self.len() == other.len() && self.iter().eq(other.iter())
+    }
+}
+impl Eq for ValueList {}
+
+impl fmt::Debug for ValueList {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+impl Serialize for ValueList {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(self.iter())
+    }
+}
+
+impl Extend<Value> for ValueList {
+    fn extend<I: IntoIterator<Item = Value>>(&mut self, iter: I) {
+        for value in iter {
+            self.push(value);
+        }
+    }
+}
+
+impl FromIterator<Value> for ValueList {
+    fn from_iter<I: IntoIterator<Item = Value>>(iter: I) -> Self {
+        let items: SmallVec<[Value; INLINE]> = iter.into_iter().collect();
+        #[cfg(feature = "persistent")]
+        if items.len() > INLINE {
+            return ValueList(ListRepr::Tree(items.into_iter().collect()));
+        }
+        ValueList(ListRepr::Inline(items))
+    }
+}
+
+#[derive(Clone, Default)]
+pub struct ValueSet(SetRepr);
+
+#[derive(Clone)]
+enum SetRepr {
+    Inline(SmallVec<[Value; INLINE]>),
+    Hashed(BigSet),
+}
+
+impl Default for SetRepr {
+    fn default() -> Self {
+        SetRepr::Inline(SmallVec::new())
+    }
+}
+
+impl ValueSet {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    pub fn len(&self) -> usize {
+        match &self.0 {
+            SetRepr::Inline(items) => items.len(),
+            SetRepr::Hashed(items) => items.len(),
+        }
+    }
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+    pub fn contains(&self, value: &Value) -> bool {
+        match &self.0 {
+            SetRepr::Inline(items) => items.contains(value),
+            SetRepr::Hashed(items) => items.contains(value),
+        }
+    }
+    // `false` when the value was already a member.
+    pub fn insert(&mut self, value: Value) -> bool {
+        match &mut self.0 {
+            SetRepr::Inline(items) if items.contains(&value) => false,
+            SetRepr::Inline(items) if items.len() < INLINE => {
+                items.push(value);
+                true
+            }
+            SetRepr::Inline(items) => {
+                let mut hashed: BigSet = items.drain(..).collect();
+                hashed.insert(value);
+                self.0 = SetRepr::Hashed(hashed);
+                true
+            }
+            #[cfg(feature = "persistent")]
+            SetRepr::Hashed(items) => items.insert(value).is_none(),
+            #[cfg(not(feature = "persistent"))]
+            SetRepr::Hashed(items) => items.insert(value),
+        }
+    }
+    pub fn remove(&mut self, value: &Value) -> bool {
+        match &mut self.0 {
+            SetRepr::Inline(items) => match items.iter().position(|v| v == value) {
+                Some(i) => {
+                    items.swap_remove(i);
+                    true
+                }
+                None => false,
+            },
+            #[cfg(feature = "persistent")]
+            SetRepr::Hashed(items) => items.remove(value).is_some(),
+            #[cfg(not(feature = "persistent"))]
+            SetRepr::Hashed(items) => items.remove(value),
+        }
+    }
+    // In no particular order, as with `HashSet`.
+    pub fn iter(&self) -> impl Iterator<Item = &Value> {
+        let (inline, hashed) = match &self.0 {
+            SetRepr::Inline(items) => (Some(items.iter()), None),
+            SetRepr::Hashed(items) => (None, Some(items.iter())),
+        };
+        inline
+            .into_iter()
+            .flatten()
+            .chain(hashed.into_iter().flatten())
+    }
+}
+
+impl PartialEq for ValueSet {
+    fn eq(&self, other: &Self) -> bool {
+        self.len() == other.len() && self.iter().all(|v| other.contains(v))
+    }
+}
+impl Eq for ValueSet {}
+
+impl fmt::Debug for ValueSet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_set().entries(self.iter()).finish()
+    }
+}
+
+impl Serialize for ValueSet {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(self.iter())
+    }
+}
+
+impl Extend<Value> for ValueSet {
+    fn extend<I: IntoIterator<Item = Value>>(&mut self, iter: I) {
+        for value in iter {
+            self.insert(value);
+        }
+    }
+}
+
+impl FromIterator<Value> for ValueSet {
+    fn from_iter<I: IntoIterator<Item = Value>>(iter: I) -> Self {
+        let mut set = ValueSet::new();
+        set.extend(iter);
+        set
+    }
+}
+
+// ===== Float wrapper =====
+#[derive(Clone, Copy, Debug, Serialize)]
+pub struct Float(pub f64);
+
+impl PartialEq for Float {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.to_bits() == other.0.to_bits()
+    }
+}
+impl Eq for Float {}
+
+impl Float {
+    // -0.0 becomes 0.0 and every NaN the one `f64::NAN`, so floats that are
+    // the same number are also the same bits. `Value::from`, literals,
+    // `jsonparse` and `setinsert` all go through here.
+    pub fn canonical(f: f64) -> Float {
+        if f.is_nan() {
+            Float(f64::NAN)
+        } else if f == 0.0 {
+            Float(0.0)
+        } else {
+            Float(f)
+        }
+    }
+}
+
+impl Hash for Float {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.0.to_bits().hash(state)
+    }
+}
+
+// How the `eq` builtin compares floats. Sets, hashing and `Value`'s own
+// `PartialEq` always go by `Float`'s equality, which is bit for bit: that is
+// what keeps hashing consistent with equality. Floats are made canonical
+// before they reach a set (see `Float::canonical`), so there NaN equals
+// itself and -0.0 is 0.0.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum FloatEq {
+    // IEEE 754 `==`: NaN equals nothing, not even itself, and -0.0 == 0.0.
+    #[default]
+    Ieee,
+    // Bit for bit, the same as containers.
+    Bitwise,
+}
+
+// ===== Integer overflow =====
+// What integer arithmetic in builtins (`sum`, `arradd`, `dateadd`, ...) does
+// when the exact result does not fit in an i64.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Overflow {
+    // A runtime error of kind `Overflow`.
+    #[default]
+    Error,
+    // Two's complement wraparound, as `i64::wrapping_add` does.
+    Wrap,
+    // Clamped to `i64::MIN` or `i64::MAX`.
+    Saturate,
+    // The nearest float instead; an intarray becomes a floatarray.
+    Float,
+}
+
+impl Overflow {
+    pub const ALL: [Overflow; 4] = [
+        Overflow::Error,
+        Overflow::Wrap,
+        Overflow::Saturate,
+        Overflow::Float,
+    ];
+    pub fn name(self) -> &'static str {
+        match self {
+            Overflow::Error => "error",
+            Overflow::Wrap => "wrap",
+            Overflow::Saturate => "saturate",
+            Overflow::Float => "float",
+        }
+    }
+    pub fn from_name(name: &str) -> Option<Overflow> {
+        Overflow::ALL.into_iter().find(|o| o.name() == name)
+    }
+}
+
+// ===== Extern (host object) =====
+// Opaque handle to a host object (DB connection, game entity, ...). Equality
+// and hashing are by identity unless the host supplies its own hooks.
+pub type ExternEq = fn(&dyn Any, &dyn Any) -> bool;
+pub type ExternHash = fn(&dyn Any, &mut dyn Hasher);
+
+// Everything sits behind one pointer, so a host object costs a `Value` no
+// more than a string does.
+#[derive(Clone)]
+pub struct Extern(Arc<ExternInner>);
+
+#[derive(Clone)]
+struct ExternInner {
+    object: Arc<dyn Any + Send + Sync>,
+    type_name: &'static str,
+    eq: Option<ExternEq>,
+    hash: Option<ExternHash>,
+}
+
+impl Extern {
+    pub fn new<T: Any + Send + Sync>(object: T) -> Self {
+        Self(Arc::new(ExternInner {
+            object: Arc::new(object),
+            type_name: std::any::type_name::<T>(),
+            eq: None,
+            hash: None,
+        }))
+    }
+    pub fn from_arc(object: Arc<dyn Any + Send + Sync>) -> Self {
+        Self(Arc::new(ExternInner {
+            object,
+            type_name: "dyn Any",
+            eq: None,
+            hash: None,
+        }))
+    }
+    // Compares and hashes by the object's own `Eq`/`Hash` impls.
+    pub fn by_value<T: Any + Send + Sync + Eq + Hash>(object: T) -> Self {
+        Self::new(object).with_hooks(
+            |a, b| match (a.downcast_ref::<T>(), b.downcast_ref::<T>()) {
+                (Some(a), Some(b)) => a == b,
+                _ => false,
+            },
+            |a, mut state| {
+                if let Some(a) = a.downcast_ref::<T>() {
+                    a.hash(&mut state);
+                }
+            },
+        )
+    }
+    pub fn with_hooks(mut self, eq: ExternEq, hash: ExternHash) -> Self {
+        let inner = Arc::make_mut(&mut self.0);
+        inner.eq = Some(eq);
+        inner.hash = Some(hash);
+        self
+    }
+    pub fn downcast_ref<T: Any>(&self) -> Option<&T> {
+        self.0.object.downcast_ref()
+    }
+    pub fn object(&self) -> &Arc<dyn Any + Send + Sync> {
+        &self.0.object
+    }
+    pub fn type_name(&self) -> &'static str {
+        self.0.type_name
+    }
+}
+
+impl PartialEq for Extern {
+    fn eq(&self, other: &Self) -> bool {
+        match self.0.eq {
+            Some(eq) => eq(self.0.object.as_ref(), other.0.object.as_ref()),
+            None => Arc::ptr_eq(&self.0.object, &other.0.object),
+        }
+    }
+}
+impl Eq for Extern {}
+impl Hash for Extern {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        match self.0.hash {
+            Some(hash) => hash(self.0.object.as_ref(), state),
+            None => (Arc::as_ptr(&self.0.object) as *const () as usize).hash(state),
+        }
+    }
+}
+// Host objects are opaque; only their type name is serialized.
+impl Serialize for Extern {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.type_name())
+    }
+}
+impl std::fmt::Debug for Extern {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Extern({})", self.type_name())
+    }
+}
+
+// ===== Atom =====
+// `:name`: a name interned once per process, so comparing and hashing two
+// atoms is comparing two pointers. Interned names are never freed, which is
+// why atoms only come from literals in programs; data from elsewhere, such
+// as a saved world, can only name atoms that already exist.
+#[derive(Clone, Copy)]
+pub struct Atom(&'static String);
+
+// Keyed by `&str` so a lookup does not allocate; the key borrows the leaked
+// `String` it maps to.
+type AtomNames = HashMap<&'static str, &'static String>;
+
+fn atom_names() -> std::sync::MutexGuard<'static, AtomNames> {
+    static NAMES: std::sync::OnceLock<std::sync::Mutex<AtomNames>> = std::sync::OnceLock::new();
+    NAMES.get_or_init(Default::default).lock().unwrap()
+}
+
+impl Atom {
+    pub fn new(name: &str) -> Atom {
+        let mut names = atom_names();
+        if let Some(&interned) = names.get(name) {
+            return Atom(interned);
+        }
+        let interned: &'static String = Box::leak(Box::new(name.to_string()));
+        names.insert(interned.as_str(), interned);
+        Atom(interned)
+    }
+    // The atom named `name` if one has been made already; never interns.
+    pub fn existing(name: &str) -> Option<Atom> {
+        atom_names().get(name).map(|&interned| Atom(interned))
+    }
+    pub fn name(&self) -> &'static str {
+        self.0
+    }
+}
+
+impl PartialEq for Atom {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::eq(self.0, other.0)
+    }
+}
+impl Eq for Atom {}
+impl Hash for Atom {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        std::ptr::hash(self.0, state)
+    }
+}
+// By name, which is consistent with `==` because each name is interned once.
+impl Ord for Atom {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0.cmp(other.0)
+    }
+}
+impl PartialOrd for Atom {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+impl Serialize for Atom {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.0)
+    }
+}
+impl std::fmt::Debug for Atom {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, ":{}", self.0)
+    }
+}
+
+// ===== Range =====
+// The integers `start, start + step, ...` short of `end`, produced on demand:
+// a range of a billion is three words until something needs it as a list.
+// Stored normalized (an empty range is `range(0, 0)`, a one-element range has
+// step 1), so ranges with the same elements are equal and hash alike.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize)]
+pub struct IntRange {
+    start: i64,
+    step: i64,
+    len: u64,
+}
+
+impl IntRange {
+    // `None` when `step` is 0.
+    pub fn new(start: i64, end: i64, step: i64) -> Option<IntRange> {
+        if step == 0 {
+            return None;
+        }
+        let span = end as i128 - start as i128;
+        let step_abs = (step as i128).abs();
+        let len = if span.signum() == (step as i128).signum() {
+            ((span.abs() + step_abs - 1) / step_abs) as u64
+        } else {
+            0
+        };
+        Some(IntRange::normalized(start, step, len))
+    }
+    fn normalized(start: i64, step: i64, len: u64) -> IntRange {
+        match len {
+            0 => IntRange {
+                start: 0,
+                step: 1,
+                len: 0,
+            },
+            1 => IntRange {
+                start,
+                step: 1,
+                len,
+            },
+            _ => IntRange { start, step, len },
+        }
+    }
+    // The inverse of `start`, `step` and `len`. `None` when the step is 0
+    // or the last element would not fit in an i64.
+    pub fn from_parts(start: i64, step: i64, len: u64) -> Option<IntRange> {
+        let last = start as i128 + (len.max(1) - 1) as i128 * step as i128;
+        if step == 0 || i64::try_from(last).is_err() {
+            return None;
+        }
+        Some(IntRange::normalized(start, step, len))
+    }
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+    pub fn start(&self) -> i64 {
+        self.start
+    }
+    pub fn step(&self) -> i64 {
+        self.step
+    }
+    // One step past the last element; outside i64 when the range runs up to
+    // its edge.
+    pub fn end(&self) -> i128 {
+        self.start as i128 + self.len as i128 * self.step as i128
+    }
+    pub fn get(&self, i: u64) -> Option<i64> {
+        (i < self.len).then(|| (self.start as i128 + i as i128 * self.step as i128) as i64)
+    }
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = i64> + '_ {
+        (0..self.len).map(|i| (self.start as i128 + i as i128 * self.step as i128) as i64)
+    }
+    // Exact: the elements average to half the first plus the last, and the
+    // product is even because an odd count has an integer middle. It fits in
+    // an i128 since every element fits in an i64.
+    pub fn sum(&self) -> i128 {
+        match self.len.checked_sub(1).and_then(|last| self.get(last)) {
+            Some(last) => self.len as i128 * (self.start as i128 + last as i128) / 2,
+            None => 0,
+        }
+    }
+    pub fn pop_front(&mut self) -> Option<i64> {
+        let first = self.get(0)?;
+        *self = IntRange::normalized(first.wrapping_add(self.step), self.step, self.len - 1);
+        Some(first)
+    }
+    pub fn pop_back(&mut self) -> Option<i64> {
+        let last = self.get(self.len.checked_sub(1)?)?;
+        *self = IntRange::normalized(self.start, self.step, self.len - 1);
+        Some(last)
+    }
+}
+
+// ===== Value =====
+// Serializes as `{"type": "int", "value": 1}`; unlike `to_json` this keeps
+// every variant distinct.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(tag = "type", content = "value", rename_all = "lowercase")]
+pub enum Value {
+    Int(i64),
+    Float(Float),
+    // Exact base-10 number, written `12.50m`.
+    #[cfg(feature = "decimal")]
+    Decimal(rust_decimal::Decimal),
+    Bool(bool),
+    // One Unicode scalar value, written `'a'`.
+    Char(char),
+    Str(Shared<String>),
+    // A name written `:idle`, for tags and enum-like states.
+    Atom(Atom),
+    List(Shared<ValueList>),
+    // A lazy sequence of integers from `range()`.
+    Range(Shared<IntRange>),
+    // Homogeneous arrays from `intarray()` and `floatarray()`, with the
+    // elements stored unboxed for the bulk builtins (`sum`, `arradd`, ...).
+    IntArray(Shared<Vec<i64>>),
+    FloatArray(Shared<Vec<Float>>),
+    Set(Shared<ValueSet>),
+    // Keys keep the order they were inserted in, so printing and
+    // serializing are stable. Equality, hashing and ordering ignore it.
+    Map(Shared<IndexMap<String, Value>>),
+    Extern(Extern),
+    // The absence of a value, e.g. `getenv` of an unset variable.
+    Nil,
+}
+
+impl Hash for Value {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        match self {
+            Value::Int(i) => i.hash(state),
+            Value::Float(f) => f.hash(state),
+            #[cfg(feature = "decimal")]
+            Value::Decimal(d) => d.hash(state),
+            Value::Bool(b) => b.hash(state),
+            Value::Char(c) => c.hash(state),
+            Value::Str(s) => s.hash(state),
+            Value::Atom(a) => a.hash(state),
+            Value::List(v) => {
+                for e in v.iter() {
+                    e.hash(state);
+                }
+            }
+            Value::Range(r) => r.hash(state),
+            Value::IntArray(a) => a.hash(state),
+            Value::FloatArray(a) => a.hash(state),
+            Value::Set(s) => {
+                let mut acc = 0u64;
+                for e in s.iter() {
+                    let mut h = std::collections::hash_map::DefaultHasher::new();
+                    e.hash(&mut h);
+                    acc ^= h.finish();
+                }
+                acc.hash(state);
+            }
+            Value::Map(m) => {
+                let mut acc = 0u64;
+                for entry in m.iter() {
+                    let mut h = std::collections::hash_map::DefaultHasher::new();
+                    entry.hash(&mut h);
+                    acc ^= h.finish();
+                }
+                acc.hash(state);
+            }
+            Value::Extern(e) => e.hash(state),
+            Value::Nil => {}
+        }
+    }
+}
+
+impl From<i64> for Value {
+    fn from(i: i64) -> Self {
+        Value::Int(i)
+    }
+}
+impl From<i32> for Value {
+    fn from(i: i32) -> Self {
+        Value::Int(i as i64)
+    }
+}
+impl From<f64> for Value {
+    fn from(f: f64) -> Self {
+        Value::Float(Float::canonical(f))
+    }
+}
+impl From<bool> for Value {
+    fn from(b: bool) -> Self {
+        Value::Bool(b)
+    }
+}
+impl From<char> for Value {
+    fn from(c: char) -> Self {
+        Value::Char(c)
+    }
+}
+impl From<&str> for Value {
+    fn from(s: &str) -> Self {
+        Value::Str(Shared::new(s.to_string()))
+    }
+}
+impl From<String> for Value {
+    fn from(s: String) -> Self {
+        Value::Str(Shared::new(s))
+    }
+}
+impl From<Vec<Value>> for Value {
+    fn from(l: Vec<Value>) -> Self {
+        Value::List(Shared::new(l.into_iter().collect()))
+    }
+}
+impl From<IntRange> for Value {
+    fn from(r: IntRange) -> Self {
+        Value::Range(Shared::new(r))
+    }
+}
+impl From<Extern> for Value {
+    fn from(e: Extern) -> Self {
+        Value::Extern(e)
+    }
+}
+impl<S> From<HashSet<Value, S>> for Value {
+    fn from(s: HashSet<Value, S>) -> Self {
+        Value::Set(Shared::new(s.into_iter().map(Value::canonical).collect()))
+    }
+}
+impl From<IndexMap<String, Value>> for Value {
+    fn from(m: IndexMap<String, Value>) -> Self {
+        Value::Map(Shared::new(m))
+    }
+}
+// Entries go in sorted by key.
+impl From<BTreeMap<String, Value>> for Value {
+    fn from(m: BTreeMap<String, Value>) -> Self {
+        Value::Map(Shared::new(m.into_iter().collect()))
+    }
+}
+
+impl Value {
+    // How error messages show a value: its type, then the value as `print`
+    // shows it, e.g. "str `hi`".
+    pub fn describe(&self) -> String {
+        match self {
+            Value::Nil => "nil".to_string(),
+            other => format!("{} `{}`", other.type_name(), other),
+        }
+    }
+    // The name scripts see from `typeof`; the same as the serialized `type`.
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Value::Int(_) => "int",
+            Value::Float(_) => "float",
+            #[cfg(feature = "decimal")]
+            Value::Decimal(_) => "decimal",
+            Value::Bool(_) => "bool",
+            Value::Char(_) => "char",
+            Value::Str(_) => "str",
+            Value::Atom(_) => "atom",
+            Value::List(_) => "list",
+            Value::Range(_) => "range",
+            Value::IntArray(_) => "intarray",
+            Value::FloatArray(_) => "floatarray",
+            Value::Set(_) => "set",
+            Value::Map(_) => "map",
+            Value::Extern(_) => "extern",
+            Value::Nil => "nil",
+        }
+    }
+    pub fn is_number(&self) -> bool {
+        match self {
+            Value::Int(_) | Value::Float(_) => true,
+            #[cfg(feature = "decimal")]
+            Value::Decimal(_) => true,
+            _ => false,
+        }
+    }
+    // The value with every float in it made canonical (see
+    // `Float::canonical`), copying only the lists and maps that hold one.
+    pub fn canonical(self) -> Value {
+        fn has_float(value: &Value) -> bool {
+            match value {
+                Value::Float(_) => true,
+                Value::List(l) => l.iter().any(has_float),
+                Value::Map(m) => m.values().any(has_float),
+                _ => false,
+            }
+        }
+        match self {
+            Value::Float(f) => Value::Float(Float::canonical(f.0)),
+            Value::List(l) if l.iter().any(has_float) => Value::List(Shared::new(
+                l.iter().cloned().map(Value::canonical).collect(),
+            )),
+            Value::Map(m) if m.values().any(has_float) => Value::Map(Shared::new(
+                m.iter()
+                    .map(|(k, v)| (k.clone(), v.clone().canonical()))
+                    .collect(),
+            )),
+            other => other,
+        }
+    }
+    // A copy sharing no list, set, map or string storage with `self`, at any
+    // depth. Host objects stay shared: only the host knows how to copy them.
+    // Scripts never mutate shared storage in place (`listpush` and
+    // `setinsert` copy first), so this matters for memory held by the host
+    // rather than for what a script can observe.
+    pub fn deep_copy(&self) -> Value {
+        match self {
+            Value::Str(s) => Value::Str(Shared::new(s.to_string())),
+            Value::List(l) => Value::List(Shared::new(l.iter().map(Value::deep_copy).collect())),
+            Value::Range(r) => Value::Range(Shared::new(**r)),
+            Value::IntArray(a) => Value::IntArray(Shared::new(a.to_vec())),
+            Value::FloatArray(a) => Value::FloatArray(Shared::new(a.to_vec())),
+            Value::Set(s) => Value::Set(Shared::new(s.iter().map(Value::deep_copy).collect())),
+            Value::Map(m) => Value::Map(Shared::new(
+                m.iter().map(|(k, v)| (k.clone(), v.deep_copy())).collect(),
+            )),
+            other => other.clone(),
+        }
+    }
+}
+
+// ===== Equality =====
+impl Value {
+    // `==`, except that floats in the value or in its lists and maps compare
+    // under `policy`. Set members are looked up by hash, so they compare bit
+    // for bit whatever the policy.
+    pub fn equals(&self, other: &Value, policy: FloatEq) -> bool {
+        match (self, other) {
+            (Value::Float(a), Value::Float(b)) if policy == FloatEq::Ieee => a.0 == b.0,
+            (Value::List(a), Value::List(b)) => {
+                a.len() == b.len() && a.iter().zip(b.iter()).all(|(a, b)| a.equals(b, policy))
+            }
+            (Value::FloatArray(a), Value::FloatArray(b)) if policy == FloatEq::Ieee => {
+                a.len() == b.len() && a.iter().zip(b.iter()).all(|(a, b)| a.0 == b.0)
+            }
+            (Value::Map(a), Value::Map(b)) => {
+                a.len() == b.len()
+                    && a.iter()
+                        .all(|(k, v)| b.get(k).is_some_and(|w| v.equals(w, policy)))
+            }
+            _ => self == other,
+        }
+    }
+}
+
+// ===== Display =====
+// What `print` and `format` show: strings, chars and atom names as they are,
+// decimals without their `m`, and everything else, including whatever a
+// collection holds, as a literal, e.g. `[1, "a"]`. `Debug` keeps the variant
+// names for dumps and the debugger.
+impl std::fmt::Display for Value {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Value::Str(s) => f.write_str(s),
+            Value::Char(c) => write!(f, "{}", c),
+            Value::Atom(a) => f.write_str(a.name()),
+            #[cfg(feature = "decimal")]
+            Value::Decimal(d) => write!(f, "{}", d),
+            other => {
+                let mut text = String::new();
+                crate::formatter::write_value(&mut text, other);
+                f.write_str(&text)
+            }
+        }
+    }
+}
+
+// ===== Ordering =====
+// `Ord` for `Value`, used by `sort` and for printing sets. Variants rank
+// nil < bool < number < char < str < atom < list < range < intarray <
+// floatarray < set < map < extern.
+// Ints and floats are all numbers and compare by value, an int first and a
+// float last when equal; floats use `f64::total_cmp`, so even NaN has a
+// place. Chars compare by code point, strings by bytes, atoms by name,
+// lists, ranges and arrays element by element, sets as their sorted
+// elements, maps as their entries sorted by key, and host objects that are
+// not equal by type name and then by address. Values compare `Equal` exactly
+// when they are `==`.
+impl Ord for Value {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.total_cmp(other)
+    }
+}
+impl PartialOrd for Value {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Value {
+    pub fn total_cmp(&self, other: &Value) -> Ordering {
+        fn rank(value: &Value) -> u8 {
+            match value {
+                Value::Nil => 0,
+                Value::Bool(_) => 1,
+                Value::Int(_) | Value::Float(_) => 2,
+                #[cfg(feature = "decimal")]
+                Value::Decimal(_) => 2,
+                Value::Char(_) => 3,
+                Value::Str(_) => 4,
+                Value::Atom(_) => 5,
+                Value::List(_) => 6,
+                Value::Range(_) => 7,
+                Value::IntArray(_) => 8,
+                Value::FloatArray(_) => 9,
+                Value::Set(_) => 10,
+                Value::Map(_) => 11,
+                Value::Extern(_) => 12,
+            }
+        }
+        fn sorted(set: &ValueSet) -> Vec<&Value> {
+            let mut items: Vec<&Value> = set.iter().collect();
+            items.sort();
+            items
+        }
+        fn entries(map: &IndexMap<String, Value>) -> Vec<(&String, &Value)> {
+            let mut entries: Vec<_> = map.iter().collect();
+            entries.sort_by(|a, b| a.0.cmp(b.0));
+            entries
+        }
+        match (self, other) {
+            (Value::Bool(a), Value::Bool(b)) => a.cmp(b),
+            (Value::Int(a), Value::Int(b)) => a.cmp(b),
+            (Value::Float(a), Value::Float(b)) => a.0.total_cmp(&b.0),
+            (Value::Int(a), Value::Float(b)) => (*a as f64).total_cmp(&b.0).then(Ordering::Less),
+            (Value::Float(a), Value::Int(b)) => a.0.total_cmp(&(*b as f64)).then(Ordering::Greater),
+            #[cfg(feature = "decimal")]
+            (Value::Decimal(a), Value::Decimal(b)) => a.cmp(b),
+            #[cfg(feature = "decimal")]
+            (Value::Decimal(a), Value::Int(b)) => a
+                .cmp(&rust_decimal::Decimal::from(*b))
+                .then(Ordering::Greater),
+            #[cfg(feature = "decimal")]
+            (Value::Int(a), Value::Decimal(b)) => {
+                rust_decimal::Decimal::from(*a).cmp(b).then(Ordering::Less)
+            }
+            #[cfg(feature = "decimal")]
+            (Value::Decimal(a), Value::Float(b)) => {
+                use rust_decimal::prelude::ToPrimitive;
+                let a = a.to_f64().unwrap_or(f64::NAN);
+                a.total_cmp(&b.0).then(Ordering::Less)
+            }
+            #[cfg(feature = "decimal")]
+            (Value::Float(a), Value::Decimal(b)) => {
+                use rust_decimal::prelude::ToPrimitive;
+                let b = b.to_f64().unwrap_or(f64::NAN);
+                a.0.total_cmp(&b).then(Ordering::Greater)
+            }
+            (Value::Char(a), Value::Char(b)) => a.cmp(b),
+            (Value::Str(a), Value::Str(b)) => a.cmp(b),
+            (Value::Atom(a), Value::Atom(b)) => a.cmp(b),
+            (Value::List(a), Value::List(b)) => a
+                .iter()
+                .zip(b.iter())
+                .map(|(a, b)| a.total_cmp(b))
+                .find(|o| o.is_ne())
+                .unwrap_or(a.len().cmp(&b.len())),
+            // Element by element, like a list, but without walking them: two
+            // ranges that agree on their first two elements agree on every
+            // element both have.
+            (Value::Range(a), Value::Range(b)) => match (a.get(0), b.get(0)) {
+                (Some(x), Some(y)) if x != y => x.cmp(&y),
+                _ => match (a.get(1), b.get(1)) {
+                    (Some(x), Some(y)) if x != y => x.cmp(&y),
+                    _ => a.len().cmp(&b.len()),
+                },
+            },
+            (Value::IntArray(a), Value::IntArray(b)) => a.cmp(b),
+            (Value::FloatArray(a), Value::FloatArray(b)) => a
+                .iter()
+                .zip(b.iter())
+                .map(|(a, b)| a.0.total_cmp(&b.0))
+                .find(|o| o.is_ne())
+                .unwrap_or(a.len().cmp(&b.len())),
+            (Value::Set(a), Value::Set(b)) => {
+                let (a, b) = (sorted(a), sorted(b));
+                a.iter()
+                    .zip(b.iter())
+                    .map(|(a, b)| a.total_cmp(b))
+                    .find(|o| o.is_ne())
+                    .unwrap_or(a.len().cmp(&b.len()))
+            }
+            (Value::Map(a), Value::Map(b)) => {
+                let (a, b) = (entries(a), entries(b));
+                a.iter()
+                    .zip(b.iter())
+                    .map(|((ka, va), (kb, vb))| ka.cmp(kb).then_with(|| va.total_cmp(vb)))
+                    .find(|o| o.is_ne())
+                    .unwrap_or(a.len().cmp(&b.len()))
+            }
+            (Value::Extern(a), Value::Extern(b)) if a == b => Ordering::Equal,
+            (Value::Extern(a), Value::Extern(b)) => {
+                a.type_name().cmp(b.type_name()).then_with(|| {
+                    Arc::as_ptr(a.object())
+                        .cast::<()>()
+                        .cmp(&Arc::as_ptr(b.object()).cast::<()>())
+                })
+            }
+            _ => rank(self).cmp(&rank(other)),
+        }
+    }
+}
+
+// ===== JSON =====
+// Sets become sorted arrays; non-finite floats and host objects become null.
+// A range becomes `{"start": .., "step": .., "len": ..}` rather than the
+// array of its elements, which could be far too long to build. The mapping is
+// lossy in that direction: `from_json` never produces a Set, a Range, a Char,
+// an Atom, a Decimal or an Extern.
+impl Value {
+    pub fn to_json(&self) -> serde_json::Value {
+        use serde_json::Value as Json;
+        match self {
+            Value::Int(i) => Json::from(*i),
+            // A string, so no digits are lost to a float on the way.
+            #[cfg(feature = "decimal")]
+            Value::Decimal(d) => Json::String(d.to_string()),
+            Value::Float(f) => serde_json::Number::from_f64(f.0)
+                .map(Json::Number)
+                .unwrap_or(Json::Null),
+            Value::Bool(b) => Json::Bool(*b),
+            Value::Char(c) => Json::String(c.to_string()),
+            Value::Atom(a) => Json::String(a.name().to_string()),
+            Value::Str(s) => Json::String(s.to_string()),
+            Value::List(l) => Json::Array(l.iter().map(Value::to_json).collect()),
+            Value::Range(r) => serde_json::json!({
+                "start": r.start(),
+                "step": r.step(),
+                "len": r.len(),
+            }),
+            Value::IntArray(a) => Json::Array(a.iter().map(|&i| Json::from(i)).collect()),
+            Value::FloatArray(a) => {
+                Json::Array(a.iter().map(|f| Value::Float(*f).to_json()).collect())
+            }
+            Value::Set(s) => {
+                let mut items: Vec<&Value> = s.iter().collect();
+                items.sort();
+                Json::Array(items.into_iter().map(Value::to_json).collect())
+            }
+            Value::Map(m) => {
+                Json::Object(m.iter().map(|(k, v)| (k.clone(), v.to_json())).collect())
+            }
+            Value::Extern(_) | Value::Nil => Json::Null,
+        }
+    }
+    pub fn from_json(json: &serde_json::Value) -> Option<Value> {
+        use serde_json::Value as Json;
+        match json {
+            Json::Bool(b) => Some(Value::Bool(*b)),
+            Json::Number(n) => match n.as_i64() {
+                Some(i) => Some(Value::Int(i)),
+                None => n.as_f64().map(Value::from),
+            },
+            Json::String(s) => Some(Value::Str(Shared::new(s.clone()))),
+            Json::Array(items) => items
+                .iter()
+                .map(Value::from_json)
+                .collect::<Option<ValueList>>()
+                .map(|items| Value::List(Shared::new(items))),
+            Json::Object(entries) => entries
+                .iter()
+                .map(|(k, v)| Some((k.clone(), Value::from_json(v)?)))
+                .collect::<Option<IndexMap<_, _>>>()
+                .map(|entries| Value::Map(Shared::new(entries))),
+            Json::Null => Some(Value::Nil),
+        }
+    }
+}
@@ -0,0 +1,1436 @@
+use crate::ast::{ASTNode, Argument, InputType, Name, PrintTarget, VisitorMut, walk_ast_mut};
+use crate::builtins::{self, Rng};
+use crate::lexer::Span;
+use crate::parser::{ParseError, parse_source, parse_statements};
+use crate::value::{FloatEq, HashState, Overflow, Shared, Value, ValueList, ValueSet};
+use indexmap::IndexMap;
+use std::{
+    collections::{HashMap, VecDeque},
+    io::BufRead,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+// ===== World =====
+// Each name gets a slot the first time it is seen, and a variable's value and
+// generation live at the same index. Slots are never moved or removed, so the
+// interpreter can remember a variable's slot by its symbol (see
+// `Interpreter::find`) and only hashes a name the first time a run touches
+// it; the by-name methods here hash on every call. A slot can hold a
+// generation without a value: merging a branch on a name that was never set
+// still counts.
+#[derive(Clone, Default)]
+pub struct World {
+    slots: HashMap<String, usize, HashState>,
+    names: Vec<String>,
+    values: Vec<Option<Value>>,
+    generations: Vec<usize>,
+}
+
+impl World {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    fn find(&self, var: &str) -> Option<usize> {
+        self.slots.get(var).copied()
+    }
+    fn slot(&mut self, var: &str) -> usize {
+        if let Some(slot) = self.find(var) {
+            return slot;
+        }
+        let slot = self.names.len();
+        self.slots.insert(var.to_string(), slot);
+        self.names.push(var.to_string());
+        self.values.push(None);
+        self.generations.push(0);
+        slot
+    }
+    pub fn get(&self, var: &str) -> Option<&Value> {
+        self.values[self.find(var)?].as_ref()
+    }
+    pub fn contains(&self, var: &str) -> bool {
+        self.get(var).is_some()
+    }
+    pub fn get_int(&self, var: &str) -> Option<i64> {
+        match self.get(var)? {
+            Value::Int(i) => Some(*i),
+            _ => None,
+        }
+    }
+    pub fn get_float(&self, var: &str) -> Option<f64> {
+        match self.get(var)? {
+            Value::Float(f) => Some(f.0),
+            _ => None,
+        }
+    }
+    pub fn get_bool(&self, var: &str) -> Option<bool> {
+        match self.get(var)? {
+            Value::Bool(b) => Some(*b),
+            _ => None,
+        }
+    }
+    pub fn get_str(&self, var: &str) -> Option<&str> {
+        match self.get(var)? {
+            Value::Str(s) => Some(s.as_str()),
+            _ => None,
+        }
+    }
+    pub fn get_list(&self, var: &str) -> Option<&ValueList> {
+        match self.get(var)? {
+            Value::List(l) => Some(l.as_ref()),
+            _ => None,
+        }
+    }
+    pub fn get_set(&self, var: &str) -> Option<&ValueSet> {
+        match self.get(var)? {
+            Value::Set(s) => Some(s.as_ref()),
+            _ => None,
+        }
+    }
+    pub fn get_map(&self, var: &str) -> Option<&IndexMap<String, Value>> {
+        match self.get(var)? {
+            Value::Map(m) => Some(m.as_ref()),
+            _ => None,
+        }
+    }
+    // Returns the previous value, if any. Only a new name allocates a key.
+    pub fn set(&mut self, var: &str, value: impl Into<Value>) -> Option<Value> {
+        let slot = self.slot(var);
+        self.values[slot].replace(value.into())
+    }
+    // Variables with a value, in the order their names were first seen.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &Value)> + Clone {
+        self.names
+            .iter()
+            .zip(&self.values)
+            .filter_map(|(k, v)| Some((k.as_str(), v.as_ref()?)))
+    }
+    pub fn get_gen(&self, var: &str) -> usize {
+        self.find(var).map_or(0, |slot| self.generations[slot])
+    }
+    fn inc_gen(&mut self, var: &str) {
+        let slot = self.slot(var);
+        self.generations[slot] += 1;
+    }
+    // Values are reference counted, so this only copies the slot tables.
+    pub fn snapshot(&self) -> WorldSnapshot {
+        WorldSnapshot(Shared::new(self.clone()))
+    }
+    pub fn restore(&mut self, snapshot: &WorldSnapshot) {
+        *self = World::clone(&snapshot.0);
+    }
+    // Every slot in order, for `to_bytes`.
+    pub(crate) fn slots(&self) -> impl ExactSizeIterator<Item = (&str, Option<&Value>, usize)> {
+        self.names
+            .iter()
+            .zip(&self.values)
+            .zip(&self.generations)
+            .map(|((k, v), &g)| (k.as_str(), v.as_ref(), g))
+    }
+    // Fills a new slot; `None` if the name already has one.
+    pub(crate) fn set_slot(
+        &mut self,
+        var: &str,
+        value: Option<Value>,
+        generation: usize,
+    ) -> Option<()> {
+        if self.find(var).is_some() {
+            return None;
+        }
+        let slot = self.slot(var);
+        self.values[slot] = value;
+        self.generations[slot] = generation;
+        Some(())
+    }
+    // Nonzero generations, with their names.
+    fn generations(&self) -> impl Iterator<Item = (&str, usize)> + Clone {
+        self.names
+            .iter()
+            .zip(&self.generations)
+            .filter(|&(_, &g)| g > 0)
+            .map(|(k, &g)| (k.as_str(), g))
+    }
+    // {"vars": {name: value, ...}, "generation": {name: n, ...}}, with names
+    // sorted.
+    pub fn to_json(&self) -> serde_json::Value {
+        let mut vars: serde_json::Map<_, _> = self
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_json()))
+            .collect();
+        vars.sort_keys();
+        let mut generation: serde_json::Map<_, _> = self
+            .generations()
+            .map(|(k, g)| (k.to_string(), serde_json::Value::from(g)))
+            .collect();
+        generation.sort_keys();
+        serde_json::json!({ "vars": vars, "generation": generation })
+    }
+    // Inverse of `to_json`; a missing "generation" object means all zero.
+    pub fn from_json(json: &serde_json::Value) -> Option<World> {
+        let mut world = World::new();
+        for (k, v) in json.get("vars")?.as_object()? {
+            world.set(k, Value::from_json(v)?);
+        }
+        if let Some(generation) = json.get("generation") {
+            for (k, g) in generation.as_object()? {
+                let slot = world.slot(k);
+                world.generations[slot] = g.as_u64()? as usize;
+            }
+        }
+        Some(world)
+    }
+}
+
+impl std::fmt::Debug for World {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("World")
+            .field("vars", &DebugMap(self.iter()))
+            .field("generation", &DebugMap(self.generations()))
+            .finish()
+    }
+}
+
+struct DebugMap<I>(I);
+
+impl<I, K, V> std::fmt::Debug for DebugMap<I>
+where
+    I: Iterator<Item = (K, V)> + Clone,
+    K: std::fmt::Debug,
+    V: std::fmt::Debug,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_map().entries(self.0.clone()).finish()
+    }
+}
+
+// Immutable, cheaply cloneable view of a World at some point in time.
+#[derive(Clone, Debug)]
+pub struct WorldSnapshot(Shared<World>);
+
+impl std::ops::Deref for WorldSnapshot {
+    type Target = World;
+    fn deref(&self) -> &World {
+        &self.0
+    }
+}
+
+// ===== Branch =====
+#[derive(Clone)]
+pub struct Branch {
+    pub(crate) variable: String,
+    pub(crate) delta: Option<Value>,
+    pub(crate) generation: usize,
+    pub(crate) nested: Vec<Branch>,
+}
+
+impl Branch {
+    pub fn new(variable: &str, delta: Option<Value>, generation: usize) -> Self {
+        Self {
+            variable: variable.to_string(),
+            delta,
+            generation,
+            nested: vec![],
+        }
+    }
+    pub fn variable(&self) -> &str {
+        &self.variable
+    }
+    pub fn generation(&self) -> usize {
+        self.generation
+    }
+    pub fn nested(&self) -> &[Branch] {
+        &self.nested
+    }
+    // Fails without touching the world if the variable of this branch or of
+    // any branch nested in it was merged by someone else since it was opened.
+    pub fn merge(
+        self,
+        world: &mut World,
+        on_change: &mut dyn FnMut(&str, Option<&Value>, &Value),
+    ) -> Result<(), RuntimeError> {
+        self.check(world, &mut HashMap::new())?;
+        self.apply(world, on_change);
+        Ok(())
+    }
+    // Walks the tree in the order `apply` merges it, counting in `merged` the
+    // generations the branches before each one will have added.
+    fn check<'a>(
+        &'a self,
+        world: &World,
+        merged: &mut HashMap<&'a str, usize>,
+    ) -> Result<(), RuntimeError> {
+        let merged_here = merged.entry(&self.variable).or_default();
+        let world_generation = world.get_gen(&self.variable) + *merged_here;
+        *merged_here += 1;
+        if world_generation != self.generation {
+            #[cfg(feature = "tracing")]
+            tracing::debug!(
+                variable = %self.variable,
+                branch_generation = self.generation,
+                world_generation,
+                "merge conflict: branch is stale"
+            );
+            return Err(RuntimeError::new(
+                RuntimeErrorKind::MergeConflict,
+                format!(
+                    "merge conflict on `{}`: branch opened at generation {}, world is at {}",
+                    self.variable, self.generation, world_generation
+                ),
+            )
+            .with_variable(&self.variable)
+            .with_generation(world_generation, Some(self.generation)));
+        }
+        self.nested
+            .iter()
+            .try_for_each(|nested| nested.check(world, merged))
+    }
+    fn apply(self, world: &mut World, on_change: &mut dyn FnMut(&str, Option<&Value>, &Value)) {
+        #[cfg(feature = "tracing")]
+        tracing::debug!(variable = %self.variable, generation = self.generation, "merge");
+        if let Some(val) = self.delta {
+            let old = world.set(&self.variable, val.clone());
+            on_change(&self.variable, old.as_ref(), &val);
+        }
+        world.inc_gen(&self.variable);
+        for nested in self.nested {
+            nested.apply(world, on_change);
+        }
+    }
+}
+
+// ===== I/O =====
+// Everything the VM reads from or writes to the outside world goes through
+// this trait, so hosts without a terminal (wasm32, servers) can plug in their own.
+pub trait Io: Send {
+    fn write_str(&mut self, text: &str);
+    fn read_line(&mut self) -> Option<String>;
+    fn flush(&mut self) {}
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+#[derive(Default)]
+pub struct StdIo;
+
+#[cfg(not(target_arch = "wasm32"))]
+impl Io for StdIo {
+    fn write_str(&mut self, text: &str) {
+        print!("{}", text);
+    }
+    fn read_line(&mut self) -> Option<String> {
+        let mut line = String::new();
+        match std::io::stdin().read_line(&mut line) {
+            Ok(0) | Err(_) => None,
+            Ok(_) => Some(line),
+        }
+    }
+    fn flush(&mut self) {
+        use std::io::Write;
+        let _ = std::io::stdout().flush();
+    }
+}
+
+// In-memory I/O. Clones share the same buffers, so the host can keep one
+// handle to feed input and collect output after handing the other to the VM.
+#[derive(Clone, Default)]
+pub struct MemoryIo {
+    output: Arc<Mutex<String>>,
+    input: Arc<Mutex<VecDeque<String>>>,
+}
+
+impl MemoryIo {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    pub fn with_input<I, S>(lines: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        let io = Self::default();
+        io.input
+            .lock()
+            .unwrap()
+            .extend(lines.into_iter().map(Into::into));
+        io
+    }
+    pub fn push_input(&self, line: &str) {
+        self.input.lock().unwrap().push_back(line.to_string());
+    }
+    pub fn output(&self) -> String {
+        self.output.lock().unwrap().clone()
+    }
+    pub fn take_output(&self) -> String {
+        std::mem::take(&mut *self.output.lock().unwrap())
+    }
+}
+
+impl Io for MemoryIo {
+    fn write_str(&mut self, text: &str) {
+        self.output.lock().unwrap().push_str(text);
+    }
+    fn read_line(&mut self) -> Option<String> {
+        self.input.lock().unwrap().pop_front()
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn default_io() -> Box<dyn Io> {
+    Box::new(StdIo)
+}
+
+#[cfg(target_arch = "wasm32")]
+fn default_io() -> Box<dyn Io> {
+    Box::new(MemoryIo::new())
+}
+
+// ===== Program =====
+// A parsed script that can be executed any number of times without re-lexing.
+// Cloning shares the AST, so tools and hot reload can hold on to a program
+// cheaply.
+#[derive(Clone, Debug)]
+pub struct Program {
+    ast: Shared<[ASTNode]>,
+    // Each variable the program mentions, at the index of its symbol.
+    symbols: Shared<[Name]>,
+}
+
+// Numbers variables in the order they are first mentioned.
+#[derive(Default)]
+struct Symbols {
+    numbers: HashMap<Name, usize>,
+    names: Vec<Name>,
+}
+
+impl VisitorMut for Symbols {
+    fn visit_variable_mut(&mut self, name: &mut Name) {
+        let symbol = *self.numbers.entry(name.clone()).or_insert_with(|| {
+            self.names.push(name.clone());
+            self.names.len() - 1
+        });
+        *name = name.with_symbol(symbol);
+    }
+}
+
+impl Program {
+    // Every lexing and syntax error is reported.
+    pub fn compile(src: &str) -> Result<Self, Vec<ParseError>> {
+        match parse_source(src) {
+            (ast, errors) if errors.is_empty() => Ok(Self::from_ast(ast)),
+            (_, errors) => Err(errors),
+        }
+    }
+    pub fn from_ast(mut ast: Vec<ASTNode>) -> Self {
+        let mut symbols = Symbols::default();
+        walk_ast_mut(&mut symbols, &mut ast);
+        Self {
+            ast: ast.into(),
+            symbols: symbols.names.into(),
+        }
+    }
+    pub fn ast(&self) -> &[ASTNode] {
+        &self.ast
+    }
+    // Every variable the program mentions, indexed by `Name::symbol`.
+    pub fn symbols(&self) -> &[Name] {
+        &self.symbols
+    }
+    // Runs against `world` with no pending branches and no observers.
+    pub fn run(&self, world: &mut World) -> Result<(), RuntimeError> {
+        let mut interpreter = Interpreter::with_world(std::mem::take(world));
+        let result = interpreter.run(self);
+        *world = interpreter.into_world();
+        result
+    }
+}
+
+// ===== Interpreter =====
+// Called with (old, new) whenever an observed variable is written.
+pub type Observer = Box<dyn FnMut(Option<&Value>, &Value) + Send>;
+
+// Fallback environment consulted when a variable is not in the world (lazy
+// config, a database, ...). `store` may take over writes; returning `false`
+// keeps the write in the world as usual.
+pub trait Resolver: Send {
+    fn resolve(&mut self, name: &str) -> Option<Value>;
+    fn store(&mut self, _name: &str, _value: &Value) -> bool {
+        false
+    }
+}
+
+impl<F> Resolver for F
+where
+    F: FnMut(&str) -> Option<Value> + Send,
+{
+    fn resolve(&mut self, name: &str) -> Option<Value> {
+        self(name)
+    }
+}
+
+// Passed to instrumentation hooks around every executed node. `step` counts
+// nodes entered so far, including the current one.
+#[derive(Debug, Clone, Copy)]
+pub struct NodeEvent {
+    pub kind: &'static str,
+    pub span: Span,
+    pub step: u64,
+}
+
+pub type NodeHook = Box<dyn FnMut(&NodeEvent) + Send>;
+
+// What a stepper sees before each node runs (see `Interpreter::set_stepper`).
+pub struct Pause<'a> {
+    pub node: &'a ASTNode,
+    pub step: u64,
+    pub world: &'a World,
+    // Spans of the `branch` statements the node is inside, outermost first.
+    pub stack: &'a [Span],
+    branches: &'a HashMap<String, Branch>,
+}
+
+impl Pause<'_> {
+    // Closed branches waiting for their `merge`.
+    pub fn branches(&self) -> impl Iterator<Item = &Branch> {
+        self.branches.values()
+    }
+    pub fn branch(&self, variable: &str) -> Option<&Branch> {
+        self.branches.get(variable)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Resume {
+    Continue,
+    // Abandon the run with a `Stopped` error.
+    Stop,
+}
+
+pub type Stepper = Box<dyn FnMut(&Pause) -> Resume + Send>;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum RuntimeErrorKind {
+    UndefinedVariable,
+    // A branch was merged after its variable had moved on (see `Branch::merge`).
+    MergeConflict,
+    TypeError,
+    // The per-run node budget set with `InterpreterBuilder::fuel` ran out.
+    FuelExhausted { steps: u64 },
+    Timeout { after: Duration },
+    // A stepper returned `Resume::Stop`.
+    Stopped,
+    // `input` found nothing left to read (see `InterpreterBuilder::require_input`).
+    InputExhausted,
+    // A line read by `input int`/`input float` did not parse.
+    InvalidInput,
+    // A call to a function that is not in `builtins::NAMES`.
+    UnknownFunction,
+    // Integer arithmetic went past the range of an i64 under
+    // `Overflow::Error`, or decimal arithmetic past the range of a decimal.
+    Overflow,
+    // A builtin got the wrong number or kind of arguments.
+    ArgumentError,
+    // An `assert` condition was not `true`.
+    AssertionFailed,
+    // A builtin that reaches outside the interpreter ran in sandbox mode.
+    Sandboxed,
+    // A builtin could not read or write a file, fetch a URL or start a
+    // process.
+    IoError,
+}
+
+// Generations of the offending variable when the error was raised. `branch`
+// is set for merge conflicts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GenerationInfo {
+    pub world: usize,
+    pub branch: Option<usize>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RuntimeError {
+    pub kind: RuntimeErrorKind,
+    pub message: String,
+    // The innermost statement being executed.
+    pub span: Option<Span>,
+    pub variable: Option<String>,
+    pub generation: Option<GenerationInfo>,
+}
+
+impl RuntimeError {
+    pub fn new(kind: RuntimeErrorKind, message: impl Into<String>) -> Self {
+        Self {
+            kind,
+            message: message.into(),
+            span: None,
+            variable: None,
+            generation: None,
+        }
+    }
+    fn with_variable(mut self, name: &str) -> Self {
+        self.variable = Some(name.to_string());
+        self
+    }
+    fn with_generation(mut self, world: usize, branch: Option<usize>) -> Self {
+        self.generation = Some(GenerationInfo { world, branch });
+        self
+    }
+}
+
+impl std::fmt::Display for RuntimeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.message)?;
+        if let Some(span) = self.span {
+            write!(f, " at {}..{}", span.start, span.end)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for RuntimeError {}
+
+// Anything that can go wrong between source text and a finished run.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Error {
+    Parse(Vec<ParseError>),
+    Runtime(RuntimeError),
+}
+
+impl From<RuntimeError> for Error {
+    fn from(e: RuntimeError) -> Self {
+        Error::Runtime(e)
+    }
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::Parse(errors) => {
+                for (i, e) in errors.iter().enumerate() {
+                    if i > 0 {
+                        writeln!(f)?;
+                    }
+                    write!(f, "{}", e)?;
+                }
+                Ok(())
+            }
+            Error::Runtime(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+// No clock on wasm32-unknown-unknown (`Instant::now` panics), so timeouts are
+// ignored there.
+#[cfg(not(target_arch = "wasm32"))]
+fn deadline_after(timeout: Duration) -> Option<Instant> {
+    Instant::now().checked_add(timeout)
+}
+
+#[cfg(target_arch = "wasm32")]
+fn deadline_after(_timeout: Duration) -> Option<Instant> {
+    None
+}
+
+// `Interpreter::slots` before a symbol's slot has been looked up.
+const UNRESOLVED: usize = usize::MAX;
+
+pub struct Interpreter {
+    world: World,
+    // Indexed by the symbols of the program being run: the world slot each
+    // one was found in, and whether it has observers.
+    slots: Vec<usize>,
+    observed: Vec<bool>,
+    pub(crate) branches: HashMap<String, Branch>,
+    observers: HashMap<String, Vec<Observer>>,
+    io: Box<dyn Io>,
+    resolver: Option<Box<dyn Resolver>>,
+    steps: u64,
+    on_enter: Option<NodeHook>,
+    on_exit: Option<NodeHook>,
+    stepper: Option<Stepper>,
+    stack: Vec<Span>,
+    fuel: Option<u64>,
+    fuel_left: Option<u64>,
+    timeout: Option<Duration>,
+    deadline: Option<Instant>,
+    exit_code: Option<i32>,
+    require_input: bool,
+    strict: bool,
+    pub(crate) sandbox: bool,
+    pub(crate) float_eq: FloatEq,
+    pub(crate) overflow: Overflow,
+    pub(crate) rng: Rng,
+}
+
+impl Default for Interpreter {
+    fn default() -> Self {
+        Self {
+            world: World::new(),
+            slots: Vec::new(),
+            observed: Vec::new(),
+            branches: HashMap::new(),
+            observers: HashMap::new(),
+            io: default_io(),
+            resolver: None,
+            steps: 0,
+            on_enter: None,
+            on_exit: None,
+            stepper: None,
+            stack: Vec::new(),
+            fuel: None,
+            fuel_left: None,
+            timeout: None,
+            deadline: None,
+            exit_code: None,
+            require_input: false,
+            strict: false,
+            sandbox: false,
+            float_eq: FloatEq::default(),
+            overflow: Overflow::default(),
+            rng: Rng::unseeded(),
+        }
+    }
+}
+
+// Collects execution options so they don't pile up as constructor arguments.
+#[derive(Default)]
+pub struct InterpreterBuilder {
+    interpreter: Interpreter,
+}
+
+impl InterpreterBuilder {
+    pub fn world(mut self, world: World) -> Self {
+        self.interpreter.world = world;
+        self
+    }
+    pub fn var(mut self, name: &str, value: impl Into<Value>) -> Self {
+        self.interpreter.world.set(name, value);
+        self
+    }
+    pub fn io<I: Io + 'static>(mut self, io: I) -> Self {
+        self.interpreter.set_io(io);
+        self
+    }
+    pub fn resolver<R: Resolver + 'static>(mut self, resolver: R) -> Self {
+        self.interpreter.set_resolver(resolver);
+        self
+    }
+    pub fn observe<F>(mut self, variable: &str, observer: F) -> Self
+    where
+        F: FnMut(Option<&Value>, &Value) + Send + 'static,
+    {
+        self.interpreter.observe(variable, observer);
+        self
+    }
+    pub fn hooks<E, X>(mut self, on_enter: E, on_exit: X) -> Self
+    where
+        E: FnMut(&NodeEvent) + Send + 'static,
+        X: FnMut(&NodeEvent) + Send + 'static,
+    {
+        self.interpreter.set_hooks(on_enter, on_exit);
+        self
+    }
+    pub fn stepper<F>(mut self, stepper: F) -> Self
+    where
+        F: FnMut(&Pause) -> Resume + Send + 'static,
+    {
+        self.interpreter.set_stepper(stepper);
+        self
+    }
+    // Seed for `rand`/`randint`, so runs are reproducible.
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.interpreter.set_seed(seed);
+        self
+    }
+    // Make `input` at end of input an error rather than an empty string.
+    pub fn require_input(mut self, require: bool) -> Self {
+        self.interpreter.require_input = require;
+        self
+    }
+    // Make reading an undefined variable with `print`, or updating one with
+    // `listpush` and friends, an error rather than a message or a no-op.
+    pub fn strict(mut self, strict: bool) -> Self {
+        self.interpreter.strict = strict;
+        self
+    }
+    // Deny builtins that reach the host: `getenv`, `exec`, file and network
+    // access.
+    pub fn sandbox(mut self, sandbox: bool) -> Self {
+        self.interpreter.sandbox = sandbox;
+        self
+    }
+    // How the `eq` builtin compares floats; IEEE 754 by default.
+    pub fn float_eq(mut self, policy: FloatEq) -> Self {
+        self.interpreter.float_eq = policy;
+        self
+    }
+    // What integer arithmetic does past the range of an i64; an error by
+    // default.
+    pub fn overflow(mut self, policy: Overflow) -> Self {
+        self.interpreter.overflow = policy;
+        self
+    }
+    // Maximum number of nodes a single `run`/`eval_str` may execute.
+    pub fn fuel(mut self, steps: u64) -> Self {
+        self.interpreter.fuel = Some(steps);
+        self
+    }
+    // Wall-clock limit per `run`/`eval_str`. Ignored on wasm32.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.interpreter.timeout = Some(timeout);
+        self
+    }
+    pub fn build(self) -> Interpreter {
+        self.interpreter
+    }
+}
+
+fn undefined_variable(name: &str) -> RuntimeError {
+    RuntimeError::new(
+        RuntimeErrorKind::UndefinedVariable,
+        format!("undefined variable `{}`", name),
+    )
+    .with_variable(name)
+}
+
+fn notify(
+    observers: &mut HashMap<String, Vec<Observer>>,
+    name: &str,
+    old: Option<&Value>,
+    new: &Value,
+) {
+    if let Some(list) = observers.get_mut(name) {
+        for observer in list.iter_mut() {
+            observer(old, new);
+        }
+    }
+}
+
+impl Interpreter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    pub fn builder() -> InterpreterBuilder {
+        InterpreterBuilder::default()
+    }
+    pub fn world(&self) -> &World {
+        &self.world
+    }
+    pub fn world_mut(&mut self) -> &mut World {
+        &mut self.world
+    }
+    pub fn set_io<I: Io + 'static>(&mut self, io: I) {
+        self.io = Box::new(io);
+    }
+    pub fn observe<F>(&mut self, variable: &str, observer: F)
+    where
+        F: FnMut(Option<&Value>, &Value) + Send + 'static,
+    {
+        self.observers
+            .entry(variable.to_string())
+            .or_default()
+            .push(Box::new(observer));
+    }
+    pub fn with_world(world: World) -> Self {
+        Self {
+            world,
+            ..Self::default()
+        }
+    }
+    // Pre-seeds the world with host-provided inputs before any script runs.
+    pub fn with_vars<I, K, V>(vars: I) -> Self
+    where
+        I: IntoIterator<Item = (K, V)>,
+        K: AsRef<str>,
+        V: Into<Value>,
+    {
+        let mut world = World::new();
+        for (k, v) in vars {
+            world.set(k.as_ref(), v);
+        }
+        Self::with_world(world)
+    }
+    pub fn into_world(self) -> World {
+        self.world
+    }
+    pub fn run(&mut self, program: &Program) -> Result<(), RuntimeError> {
+        self.begin_run();
+        self.load_symbols(&program.symbols);
+        self.execute_ast(&program.ast, false).map(|_| ())
+    }
+    // Lexes, parses and runs `src` against the current world, returning the
+    // value of the last statement (the assigned value for `let`, the printed
+    // value for `print`, ...).
+    pub fn eval_str(&mut self, src: &str) -> Result<Option<Value>, Error> {
+        let program = Program::compile(src).map_err(Error::Parse)?;
+        Ok(self.eval(&program)?)
+    }
+    // Reads a script a line at a time and runs its top-level statements as
+    // soon as they are complete, so neither the source nor its AST is ever
+    // held whole. Spans count bytes from the start of the stream. Stops at
+    // the first syntax error: nothing after it can be trusted to line up.
+    pub fn run_reader<R: BufRead>(&mut self, mut reader: R) -> Result<(), Error> {
+        self.begin_run();
+        let (mut pending, mut base) = (String::new(), 0);
+        loop {
+            let read = reader.read_line(&mut pending).map_err(|e| {
+                RuntimeError::new(
+                    RuntimeErrorKind::IoError,
+                    format!("cannot read the script: {}", e),
+                )
+            })?;
+            let finished = read == 0;
+            let Some((ast, errors)) = parse_statements(&pending, base, finished) else {
+                continue;
+            };
+            if !errors.is_empty() {
+                return Err(Error::Parse(errors));
+            }
+            base += pending.len();
+            pending.clear();
+            let program = Program::from_ast(ast);
+            self.load_symbols(&program.symbols);
+            self.execute_ast(&program.ast, false)?;
+            if finished || self.exit_code.is_some() {
+                return Ok(());
+            }
+        }
+    }
+    // Like `run`, but returns the value of the last statement.
+    pub fn eval(&mut self, program: &Program) -> Result<Option<Value>, RuntimeError> {
+        self.begin_run();
+        self.load_symbols(&program.symbols);
+        self.execute_ast(&program.ast, true)
+    }
+    // Branches that have been closed but not merged yet.
+    pub fn branches(&self) -> impl Iterator<Item = &Branch> {
+        self.branches.values()
+    }
+    fn begin_run(&mut self) {
+        self.exit_code = None;
+        self.stack.clear();
+        self.fuel_left = self.fuel;
+        self.deadline = self.timeout.and_then(deadline_after);
+    }
+    // Forgets the slots found for the last program: the world may have been
+    // replaced since, and symbols are numbered per program.
+    fn load_symbols(&mut self, symbols: &[Name]) {
+        self.slots.clear();
+        self.slots.resize(symbols.len(), UNRESOLVED);
+        self.observed.clear();
+        let observers = &self.observers;
+        self.observed.extend(
+            symbols
+                .iter()
+                .map(|name| observers.contains_key(name.as_str())),
+        );
+    }
+    // Set when the last run stopped at an `exit` statement.
+    pub fn exit_code(&self) -> Option<i32> {
+        self.exit_code
+    }
+    // For profilers, debuggers and coverage tools.
+    pub fn set_hooks<E, X>(&mut self, on_enter: E, on_exit: X)
+    where
+        E: FnMut(&NodeEvent) + Send + 'static,
+        X: FnMut(&NodeEvent) + Send + 'static,
+    {
+        self.on_enter = Some(Box::new(on_enter));
+        self.on_exit = Some(Box::new(on_exit));
+    }
+    pub fn clear_hooks(&mut self) {
+        self.on_enter = None;
+        self.on_exit = None;
+    }
+    // Called before every node with a view of the paused state; single-step
+    // UIs and debuggers block in it.
+    pub fn set_stepper<F>(&mut self, stepper: F)
+    where
+        F: FnMut(&Pause) -> Resume + Send + 'static,
+    {
+        self.stepper = Some(Box::new(stepper));
+    }
+    pub fn clear_stepper(&mut self) {
+        self.stepper = None;
+    }
+    pub fn set_seed(&mut self, seed: u64) {
+        self.rng = Rng::new(seed);
+    }
+    pub fn steps(&self) -> u64 {
+        self.steps
+    }
+    pub fn set_resolver<R: Resolver + 'static>(&mut self, resolver: R) {
+        self.resolver = Some(Box::new(resolver));
+    }
+    // Reads a variable from the world, falling back to the resolver.
+    pub fn lookup(&mut self, name: &str) -> Option<Value> {
+        if let Some(value) = self.world.get(name) {
+            return Some(value.clone());
+        }
+        self.resolver.as_mut()?.resolve(name)
+    }
+    // `lookup` for a name in the running program.
+    fn read(&mut self, name: &Name) -> Option<Value> {
+        if let Some(value) = self
+            .find(name)
+            .and_then(|slot| self.world.values[slot].as_ref())
+        {
+            return Some(value.clone());
+        }
+        self.resolver.as_mut()?.resolve(name)
+    }
+    // The world slot of a name in the running program, if it has one. The
+    // first call for each symbol asks the world; later ones index
+    // `self.slots`. Names without a symbol always ask the world.
+    fn find(&mut self, name: &Name) -> Option<usize> {
+        let Some(known) = name.symbol().and_then(|symbol| self.slots.get_mut(symbol)) else {
+            return self.world.find(name);
+        };
+        if *known == UNRESOLVED {
+            *known = self.world.find(name)?;
+        }
+        Some(*known)
+    }
+    fn observed(&self, name: &Name) -> bool {
+        match name.symbol().and_then(|symbol| self.observed.get(symbol)) {
+            Some(&observed) => observed,
+            None => self.observers.contains_key(name.as_str()),
+        }
+    }
+    // Writes a variable as a script's `let` does: through the resolver, and
+    // telling any observers.
+    pub fn assign(&mut self, name: &str, value: Value) {
+        let slot = self.world.find(name);
+        let observed = self.observers.contains_key(name);
+        self.store(name, slot, observed, value);
+    }
+    // `assign` for a name in the running program.
+    fn assign_name(&mut self, name: &Name, value: Value) {
+        let (slot, observed) = (self.find(name), self.observed(name));
+        if let Some(slot) = self.store(name, slot, observed, value)
+            && let Some(known) = name.symbol().and_then(|symbol| self.slots.get_mut(symbol))
+        {
+            *known = slot;
+        }
+    }
+    // Writes `value` to the variable at `slot`, or to a new slot for `name`
+    // unless the resolver takes it. Returns the slot written.
+    fn store(
+        &mut self,
+        name: &str,
+        slot: Option<usize>,
+        observed: bool,
+        value: Value,
+    ) -> Option<usize> {
+        if let Some(resolver) = self.resolver.as_mut()
+            && slot.is_none_or(|slot| self.world.values[slot].is_none())
+        {
+            let old = resolver.resolve(name);
+            if resolver.store(name, &value) {
+                notify(&mut self.observers, name, old.as_ref(), &value);
+                return None;
+            }
+        }
+        let slot = slot.unwrap_or_else(|| self.world.slot(name));
+        let old = self.world.values[slot].replace(value);
+        if observed && let Some(new) = &self.world.values[slot] {
+            notify(&mut self.observers, name, old.as_ref(), new);
+        }
+        Some(slot)
+    }
+    // Assigns `value` and hands it back as the statement's result when the
+    // caller wants one, so the common case moves it without a clone.
+    fn assign_result(&mut self, name: &Name, value: Value, keep: bool) -> Option<Value> {
+        let result = keep.then(|| value.clone());
+        self.assign_name(name, value);
+        result
+    }
+    // Like `lookup`, but leaves `Nil` behind in the interpreter's own world
+    // so the caller holds the only reference there is to a collection
+    // nothing else shares, and `Shared::make_mut` on it does not copy. Must be
+    // followed by `assign`. Observed variables keep their value, since the
+    // observers are owed the old one.
+    fn take_for_update(&mut self, name: &Name) -> Option<Value> {
+        if self.observed(name) {
+            return self.read(name);
+        }
+        match self
+            .find(name)
+            .and_then(|slot| self.world.values[slot].as_mut())
+        {
+            Some(value) => Some(std::mem::replace(value, Value::Nil)),
+            None => self.read(name),
+        }
+    }
+}
+
+// ===== AST実行 =====
+impl Interpreter {
+    // Returns the value produced by the last statement when `keep_last` is
+    // set. Other statements produce no result at all, so a `let` inside a
+    // long script never clones its value just to drop it again.
+    fn execute_ast(
+        &mut self,
+        ast: &[ASTNode],
+        keep_last: bool,
+    ) -> Result<Option<Value>, RuntimeError> {
+        let mut last = None;
+        for (i, node) in ast.iter().enumerate() {
+            if self.exit_code.is_some() {
+                break;
+            }
+            last = self.execute_node(node, keep_last && i + 1 == ast.len())?;
+        }
+        Ok(last)
+    }
+
+    fn check_limits(&mut self) -> Result<(), RuntimeError> {
+        if let Some(fuel) = self.fuel_left.as_mut() {
+            if *fuel == 0 {
+                let steps = self.fuel.unwrap_or(0);
+                return Err(RuntimeError::new(
+                    RuntimeErrorKind::FuelExhausted { steps },
+                    format!("fuel exhausted after {} steps", steps),
+                ));
+            }
+            *fuel -= 1;
+        }
+        if let (Some(deadline), Some(after)) = (self.deadline, self.timeout)
+            && Instant::now() >= deadline
+        {
+            return Err(RuntimeError::new(
+                RuntimeErrorKind::Timeout { after },
+                format!("timed out after {:?}", after),
+            ));
+        }
+        Ok(())
+    }
+
+    fn execute_node(&mut self, node: &ASTNode, keep: bool) -> Result<Option<Value>, RuntimeError> {
+        self.execute_node_inner(node, keep).map_err(|mut e| {
+            e.span.get_or_insert(node.span());
+            e
+        })
+    }
+
+    fn execute_node_inner(
+        &mut self,
+        node: &ASTNode,
+        keep: bool,
+    ) -> Result<Option<Value>, RuntimeError> {
+        self.check_limits()?;
+        self.steps += 1;
+        let event = NodeEvent {
+            kind: node.kind(),
+            span: node.span(),
+            step: self.steps,
+        };
+        #[cfg(feature = "tracing")]
+        let _span = tracing::trace_span!(
+            "stmt",
+            kind = event.kind,
+            start = event.span.start,
+            end = event.span.end,
+            step = event.step
+        )
+        .entered();
+        if let Some(stepper) = self.stepper.as_mut() {
+            let pause = Pause {
+                node,
+                step: self.steps,
+                world: &self.world,
+                stack: &self.stack,
+                branches: &self.branches,
+            };
+            if stepper(&pause) == Resume::Stop {
+                return Err(RuntimeError::new(
+                    RuntimeErrorKind::Stopped,
+                    format!("stopped before step {}", self.steps),
+                ));
+            }
+        }
+        if let Some(hook) = self.on_enter.as_mut() {
+            hook(&event);
+        }
+        let result = self.dispatch(node, keep)?;
+        if let Some(hook) = self.on_exit.as_mut() {
+            hook(&NodeEvent {
+                step: self.steps,
+                ..event
+            });
+        }
+        Ok(result)
+    }
+
+    // `keep` says whether the caller wants the statement's value back.
+    fn dispatch(&mut self, node: &ASTNode, keep: bool) -> Result<Option<Value>, RuntimeError> {
+        Ok(match node {
+            ASTNode::Let { name, value, .. } => self.assign_result(name, value.clone(), keep),
+            ASTNode::Branch { variable, body, .. } => {
+                let generation = self
+                    .find(variable)
+                    .map_or(0, |slot| self.world.generations[slot]);
+                #[cfg(feature = "tracing")]
+                tracing::debug!(variable = %variable, generation, "branch open");
+                let mut b = Branch::new(variable, None, generation);
+                self.stack.push(node.span());
+                let result = self.execute_ast(body, false);
+                self.stack.pop();
+                result?;
+                b.nested.extend(self.branches.drain().map(|(_, v)| v));
+                self.branches.insert(variable.to_string(), b);
+                None
+            }
+            ASTNode::Exit { code, .. } => {
+                self.exit_code = Some(*code);
+                None
+            }
+            ASTNode::Assert {
+                condition, message, ..
+            } => {
+                let value = self.argument(condition)?;
+                if value != Value::Bool(true) {
+                    let what = match condition {
+                        Argument::Variable(name) if value == Value::Bool(false) => {
+                            format!("`{}`", name)
+                        }
+                        Argument::Variable(name) => {
+                            format!("`{}` is {}, not a bool", name, value.describe())
+                        }
+                        Argument::Value(Value::Bool(false)) => "false".to_string(),
+                        Argument::Value(value) => format!("{} is not a bool", value.describe()),
+                    };
+                    let mut error = RuntimeError::new(
+                        RuntimeErrorKind::AssertionFailed,
+                        format!("assertion failed: {}", message.as_ref().unwrap_or(&what)),
+                    );
+                    if let Argument::Variable(name) = condition {
+                        error = error.with_variable(name);
+                    }
+                    return Err(error);
+                }
+                None
+            }
+            ASTNode::Call {
+                target,
+                function,
+                args,
+                ..
+            } => {
+                let mut values = Vec::with_capacity(args.len());
+                for arg in args {
+                    values.push(self.argument(arg)?);
+                }
+                match (target, builtins::call(self, function, &values)?) {
+                    (Some(target), Some(value)) => self.assign_result(target, value, keep),
+                    (Some(_), None) => {
+                        return Err(RuntimeError::new(
+                            RuntimeErrorKind::ArgumentError,
+                            format!("`{}` does not return a value", function),
+                        ));
+                    }
+                    (None, result) => result.filter(|_| keep),
+                }
+            }
+            ASTNode::Merge { variable, .. } => {
+                if let Some(b) = self.branches.remove(&**variable) {
+                    let observers = &mut self.observers;
+                    b.merge(&mut self.world, &mut |name, old, new| {
+                        notify(observers, name, old, new)
+                    })?;
+                }
+                if keep { self.read(variable) } else { None }
+            }
+            ASTNode::Print { target, .. } => {
+                let printed = match target {
+                    PrintTarget::Variable(var) => {
+                        if let Some(val) = self.read(var) {
+                            self.io.write_str(&format!("{}\n", val));
+                            keep.then_some(val)
+                        } else {
+                            if self.strict {
+                                return Err(undefined_variable(var));
+                            }
+                            self.io
+                                .write_str(&format!("(undefined variable {})\n", var));
+                            None
+                        }
+                    }
+                    PrintTarget::Value(val) => {
+                        self.io.write_str(&format!("{}\n", val));
+                        keep.then(|| val.clone())
+                    }
+                };
+                #[cfg(feature = "tracing")]
+                tracing::debug!(value = ?printed, "print");
+                printed
+            }
+            ASTNode::Input {
+                prompt,
+                input_type,
+                variable,
+                ..
+            } => {
+                if let Some(msg) = prompt {
+                    self.io.write_str(msg);
+                    self.io.flush();
+                }
+                let input = match self.io.read_line() {
+                    Some(line) => line,
+                    None if self.require_input => {
+                        return Err(RuntimeError::new(
+                            RuntimeErrorKind::InputExhausted,
+                            format!("no input left for `{}`", variable),
+                        )
+                        .with_variable(variable));
+                    }
+                    None => String::new(),
+                };
+                let input = input.trim();
+                let value = match input_type {
+                    InputType::Str => Some(Value::from(input)),
+                    InputType::Int => input.parse::<i64>().ok().map(Value::Int),
+                    InputType::Float => input.parse::<f64>().ok().map(Value::from),
+                };
+                let Some(value) = value else {
+                    return Err(RuntimeError::new(
+                        RuntimeErrorKind::InvalidInput,
+                        format!(
+                            "`{}` expects {}, got {:?}",
+                            variable,
+                            match input_type {
+                                InputType::Int => "an integer",
+                                _ => "a number",
+                            },
+                            input
+                        ),
+                    )
+                    .with_variable(variable));
+                };
+                #[cfg(feature = "tracing")]
+                tracing::debug!(variable = %variable, value = ?value, "input");
+                self.assign_result(variable, value, keep)
+            }
+            ASTNode::ListPush {
+                variable, value, ..
+            } => match self.take_for_update(variable) {
+                Some(Value::List(mut l)) => {
+                    Shared::make_mut(&mut l).push(value.clone());
+                    self.assign_result(variable, Value::List(l), keep)
+                }
+                Some(other) => return Err(self.restore_type_error(variable, "list", other)),
+                None => return self.missing(variable),
+            },
+            ASTNode::ListPushFront {
+                variable, value, ..
+            } => match self.take_for_update(variable) {
+                Some(Value::List(mut l)) => {
+                    Shared::make_mut(&mut l).push_front(value.clone());
+                    self.assign_result(variable, Value::List(l), keep)
+                }
+                Some(other) => return Err(self.restore_type_error(variable, "list", other)),
+                None => return self.missing(variable),
+            },
+            ASTNode::ListPopFront {
+                variable, target, ..
+            }
+            | ASTNode::ListPopBack {
+                variable, target, ..
+            } => match self.take_for_update(variable) {
+                Some(Value::List(mut l)) => {
+                    let element = if l.is_empty() {
+                        None
+                    } else if let ASTNode::ListPopFront { .. } = node {
+                        Shared::make_mut(&mut l).pop_front()
+                    } else {
+                        Shared::make_mut(&mut l).pop()
+                    };
+                    // Put the list back even when it was empty, since
+                    // `take_for_update` left `Nil` in its place.
+                    match element {
+                        Some(_) => self.assign_name(variable, Value::List(l)),
+                        None => self.restore(variable, Value::List(l)),
+                    }
+                    self.assign_result(target, element.unwrap_or(Value::Nil), keep)
+                }
+                Some(Value::Range(mut r)) => {
+                    let element = if let ASTNode::ListPopFront { .. } = node {
+                        Shared::make_mut(&mut r).pop_front()
+                    } else {
+                        Shared::make_mut(&mut r).pop_back()
+                    };
+                    match element {
+                        Some(_) => self.assign_name(variable, Value::Range(r)),
+                        None => self.restore(variable, Value::Range(r)),
+                    }
+                    self.assign_result(target, element.map_or(Value::Nil, Value::Int), keep)
+                }
+                Some(other) => {
+                    return Err(self.restore_type_error(variable, "list or range", other));
+                }
+                None => return self.missing(variable),
+            },
+            ASTNode::SetInsert {
+                variable, value, ..
+            } => match self.take_for_update(variable) {
+                Some(Value::Set(mut s)) => {
+                    Shared::make_mut(&mut s).insert(value.clone().canonical());
+                    self.assign_result(variable, Value::Set(s), keep)
+                }
+                Some(other) => return Err(self.restore_type_error(variable, "set", other)),
+                None => return self.missing(variable),
+            },
+        })
+    }
+
+    fn argument(&mut self, argument: &Argument) -> Result<Value, RuntimeError> {
+        match argument {
+            Argument::Value(value) => Ok(value.clone()),
+            Argument::Variable(name) => self.read(name).ok_or_else(|| undefined_variable(name)),
+        }
+    }
+
+    // A collection update on a variable that does not exist: nothing happens
+    // unless the interpreter is strict.
+    fn missing(&self, variable: &str) -> Result<Option<Value>, RuntimeError> {
+        if self.strict {
+            return Err(undefined_variable(variable));
+        }
+        Ok(None)
+    }
+
+    // Undoes `take_for_update` without notifying anyone: the value did not
+    // change.
+    fn restore(&mut self, variable: &Name, value: Value) {
+        if let Some(slot) = self.find(variable)
+            && let Some(old) = self.world.values[slot].as_mut()
+        {
+            *old = value;
+        }
+    }
+
+    fn restore_type_error(
+        &mut self,
+        variable: &Name,
+        expected: &str,
+        found: Value,
+    ) -> RuntimeError {
+        let error = self.type_error(variable, expected, &found);
+        self.restore(variable, found);
+        error
+    }
+
+    fn type_error(&self, variable: &str, expected: &str, found: &Value) -> RuntimeError {
+        RuntimeError::new(
+            RuntimeErrorKind::TypeError,
+            format!(
+                "`{}` is not a {}: it is {}",
+                variable,
+                expected,
+                found.describe()
+            ),
+        )
+        .with_variable(variable)
+        .with_generation(self.world.get_gen(variable), None)
+    }
+}
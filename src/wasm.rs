@@ -0,0 +1,93 @@
+// ===== wasm-bindgen API =====
+// Values and worlds cross the JS boundary as JSON strings (see
+// `Value::to_json` / `World::to_json`).
+use crate::{Interpreter, MemoryIo, Program, Value, World};
+use wasm_bindgen::prelude::*;
+
+fn parse_json(src: &str) -> Result<serde_json::Value, JsError> {
+    serde_json::from_str(src).map_err(|e| JsError::new(&e.to_string()))
+}
+
+/// Runs `source` against the world described by `world_json` (an empty
+/// string means a fresh world) and returns the resulting world as JSON.
+#[wasm_bindgen]
+pub fn eval(source: &str, world_json: &str) -> Result<String, JsError> {
+    let mut world = if world_json.trim().is_empty() {
+        World::new()
+    } else {
+        World::from_json(&parse_json(world_json)?)
+            .ok_or_else(|| JsError::new("invalid world JSON"))?
+    };
+    let program = Program::compile(source)
+        .map_err(|errors| JsError::new(&crate::Error::Parse(errors).to_string()))?;
+    program
+        .run(&mut world)
+        .map_err(|e| JsError::new(&e.to_string()))?;
+    Ok(world.to_json().to_string())
+}
+
+/// A long-lived interpreter for incremental use (playgrounds, consoles).
+/// `print` output is buffered and collected with `takeOutput`.
+#[wasm_bindgen]
+pub struct Vm {
+    interpreter: Interpreter,
+    io: MemoryIo,
+}
+
+#[wasm_bindgen]
+impl Vm {
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> Vm {
+        let io = MemoryIo::new();
+        let mut interpreter = Interpreter::new();
+        interpreter.set_io(io.clone());
+        Vm { interpreter, io }
+    }
+
+    /// Evaluates `source` and returns the last statement's value as JSON.
+    pub fn eval(&mut self, source: &str) -> Result<String, JsError> {
+        let value = self
+            .interpreter
+            .eval_str(source)
+            .map_err(|e| JsError::new(&e.to_string()))?;
+        Ok(value
+            .map(|v| v.to_json())
+            .unwrap_or(serde_json::Value::Null)
+            .to_string())
+    }
+
+    /// Queues a line for the next `input` statement.
+    #[wasm_bindgen(js_name = pushInput)]
+    pub fn push_input(&self, line: &str) {
+        self.io.push_input(line);
+    }
+
+    #[wasm_bindgen(js_name = takeOutput)]
+    pub fn take_output(&self) -> String {
+        self.io.take_output()
+    }
+
+    pub fn get(&self, name: &str) -> Option<String> {
+        self.interpreter
+            .world()
+            .get(name)
+            .map(|v| v.to_json().to_string())
+    }
+
+    pub fn set(&mut self, name: &str, json: &str) -> Result<(), JsError> {
+        let value = Value::from_json(&parse_json(json)?)
+            .ok_or_else(|| JsError::new("value has no sntvm equivalent"))?;
+        self.interpreter.assign(name, value);
+        Ok(())
+    }
+
+    pub fn world(&self) -> String {
+        self.interpreter.world().to_json().to_string()
+    }
+}
+
+impl Default for Vm {
+    fn default() -> Self {
+        Self::new()
+    }
+}